@@ -0,0 +1,166 @@
+use burn::{
+    data::{dataloader::batcher::Batcher, dataset::Dataset},
+    prelude::*,
+    tensor::backend::AutodiffBackend,
+};
+
+use crate::{
+    cli::ExplainArgs,
+    data::{TitanicBatch, TitanicBatcher},
+    dataset::{TitanicDataset, TitanicItem},
+    features::FeatureSchema,
+    inference::load_model,
+    model::Model,
+};
+
+/// How much every feature contributed to one prediction, keyed by the schema's feature names. The scores of features
+/// spanning several input columns, like one-hots, are summed over their columns.
+#[derive(Debug, Clone)]
+pub struct Attribution {
+    /// Absolute gradient of the logit margin with respect to the feature
+    pub saliency: Vec<(String, f32)>,
+    /// Gradient times the scaled input value
+    pub gradient_input: Vec<(String, f32)>,
+    /// Integrated Gradients from the all-zero input, in the scaled space the model sees
+    pub integrated_gradients: Vec<(String, f32)>,
+}
+
+/// Gradient of the margin of the "transported" logit over the "not transported" one, for every input column
+fn margin_gradients<B: AutodiffBackend>(
+    model: &Model<B>,
+    inputs: Tensor<B, 2>,
+    categories: Tensor<B, 2, Int>,
+) -> Vec<f32> {
+    let [batch_size, _] = inputs.dims();
+    let inputs = inputs.require_grad();
+
+    let output = model.forward(inputs.clone(), categories);
+    let margin = output.clone().slice([0..batch_size, 1..2]) - output.slice([0..batch_size, 0..1]);
+    let gradients = margin.sum().backward();
+
+    inputs
+        .grad(&gradients)
+        .expect("Inputs should have a gradient")
+        .into_data()
+        .convert::<f32>()
+        .value
+}
+
+/// Sums the input columns of every feature, for every row of `columns`
+fn per_feature(schema: &FeatureSchema, columns: &[f32]) -> Vec<Vec<(String, f32)>> {
+    columns
+        .chunks(schema.num_features())
+        .map(|row| {
+            let mut offset = 0;
+            schema
+                .features
+                .iter()
+                .map(|feature| {
+                    let score = row[offset..offset + feature.width].iter().sum();
+                    offset += feature.width;
+                    (feature.name.clone(), score)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Saliency, gradient×input and Integrated Gradients of every passenger in `batch`. Only the encoded features are
+/// attributed, the category indices the embedding tables look up are held fixed. Models with batch norm are refused,
+/// since on an autodiff backend it normalizes with the statistics of the scaled batch at every step of the path.
+pub fn attribute<B: AutodiffBackend>(
+    model: &Model<B>,
+    schema: &FeatureSchema,
+    batch: TitanicBatch<B>,
+    steps: usize,
+) -> Vec<Attribution> {
+    assert!(
+        !model.uses_batch_norm(),
+        "Attribution doesn't support models with batch norm"
+    );
+
+    let inputs: Vec<f32> = batch.inputs.clone().into_data().convert::<f32>().value;
+    let gradients = margin_gradients(model, batch.inputs.clone(), batch.categories.clone());
+
+    // Riemann sum over the midpoints of the path from the zero baseline to the inputs
+    let steps = steps.max(1);
+    let mut integrated = vec![0.0; inputs.len()];
+    for step in 0..steps {
+        let alpha = (step as f32 + 0.5) / steps as f32;
+        let path = margin_gradients(
+            model,
+            batch.inputs.clone().mul_scalar(alpha),
+            batch.categories.clone(),
+        );
+        for (total, gradient) in integrated.iter_mut().zip(path) {
+            *total += gradient / steps as f32;
+        }
+    }
+
+    let saliency: Vec<f32> = gradients.iter().map(|gradient| gradient.abs()).collect();
+    let gradient_input: Vec<f32> = gradients
+        .iter()
+        .zip(inputs.iter())
+        .map(|(gradient, input)| gradient * input)
+        .collect();
+    let integrated: Vec<f32> = integrated
+        .iter()
+        .zip(inputs.iter())
+        .map(|(gradient, input)| gradient * input)
+        .collect();
+
+    per_feature(schema, &saliency)
+        .into_iter()
+        .zip(per_feature(schema, &gradient_input))
+        .zip(per_feature(schema, &integrated))
+        .map(
+            |((saliency, gradient_input), integrated_gradients)| Attribution {
+                saliency,
+                gradient_input,
+                integrated_gradients,
+            },
+        )
+        .collect()
+}
+
+/// Prints the attributions of every submission passenger as CSV, one row per passenger and method
+pub fn explain<B: AutodiffBackend>(args: &ExplainArgs, device: B::Device) {
+    let artifacts = load_model::<B>(&args.run.artifact_dir, &device);
+    let dataset = TitanicDataset::submission(
+        &args.run.data.paths(),
+        args.run.data.bad_rows,
+        &artifacts.config.split,
+        &artifacts.imputation,
+        &artifacts.config.cabins,
+        artifacts.config.seed,
+    )
+    .unwrap_or_else(|err| panic!("Failed to load the submission data: {err}"));
+
+    let items: Vec<TitanicItem> = dataset.iter().collect();
+    let batcher = TitanicBatcher::new(device, artifacts.schema.clone(), artifacts.scaler);
+    let attributions = attribute(
+        &artifacts.model,
+        &artifacts.schema,
+        batcher.batch(items.clone()),
+        args.steps,
+    );
+
+    let names: Vec<&str> = artifacts
+        .schema
+        .features
+        .iter()
+        .map(|feature| feature.name.as_str())
+        .collect();
+    println!("PassengerId,Method,{}", names.join(","));
+
+    for (item, attribution) in items.iter().zip(attributions.iter()) {
+        for (method, scores) in [
+            ("Saliency", &attribution.saliency),
+            ("GradientInput", &attribution.gradient_input),
+            ("IntegratedGradients", &attribution.integrated_gradients),
+        ] {
+            let scores: Vec<String> = scores.iter().map(|(_, score)| score.to_string()).collect();
+            println!("{},{method},{}", item.passenger_id, scores.join(","));
+        }
+    }
+}
@@ -0,0 +1,90 @@
+#[cfg(feature = "tch")]
+use burn::backend::libtorch::LibTorchDevice;
+#[cfg(feature = "ndarray")]
+use burn::backend::ndarray::NdArrayDevice;
+#[cfg(feature = "wgpu")]
+use burn::backend::wgpu::WgpuDevice;
+use clap::{error::ErrorKind, CommandFactory, ValueEnum};
+
+use crate::cli::Cli;
+
+#[cfg(not(any(feature = "ndarray", feature = "tch", feature = "wgpu")))]
+compile_error!("At least one backend feature must be enabled: ndarray, tch or wgpu");
+
+/// Backends compiled into the binary, selected with the `ndarray`, `tch` and `wgpu` features
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum BackendKind {
+    /// Pure Rust CPU backend
+    #[cfg(feature = "ndarray")]
+    #[value(name = "ndarray")]
+    NdArray,
+    /// LibTorch, requires a libtorch install
+    #[cfg(feature = "tch")]
+    Tch,
+    /// WebGPU through wgpu
+    #[cfg(feature = "wgpu")]
+    Wgpu,
+}
+
+impl Default for BackendKind {
+    #[allow(unreachable_code)]
+    fn default() -> Self {
+        #[cfg(feature = "ndarray")]
+        return BackendKind::NdArray;
+        #[cfg(feature = "tch")]
+        return BackendKind::Tch;
+        #[cfg(feature = "wgpu")]
+        return BackendKind::Wgpu;
+    }
+}
+
+/// Splits a device string like `cuda:1` into its kind and index, the index defaults to 0
+fn split_device(device: &str) -> (String, usize) {
+    match device.split_once(':') {
+        Some((kind, index)) => match index.parse() {
+            Ok(index) => (kind.to_lowercase(), index),
+            Err(_) => invalid_device(device),
+        },
+        None => (device.to_lowercase(), 0),
+    }
+}
+
+fn invalid_device(device: &str) -> ! {
+    Cli::command()
+        .error(
+            ErrorKind::InvalidValue,
+            format!("Invalid device for the selected backend: {device}"),
+        )
+        .exit()
+}
+
+#[cfg(feature = "ndarray")]
+pub fn ndarray_device(device: &str) -> NdArrayDevice {
+    match split_device(device) {
+        (kind, _) if kind == "cpu" => NdArrayDevice::Cpu,
+        _ => invalid_device(device),
+    }
+}
+
+#[cfg(feature = "tch")]
+pub fn libtorch_device(device: &str) -> LibTorchDevice {
+    match split_device(device) {
+        (kind, _) if kind == "cpu" => LibTorchDevice::Cpu,
+        (kind, index) if kind == "cuda" => LibTorchDevice::Cuda(index),
+        (kind, _) if kind == "mps" => LibTorchDevice::Mps,
+        (kind, _) if kind == "vulkan" => LibTorchDevice::Vulkan,
+        _ => invalid_device(device),
+    }
+}
+
+#[cfg(feature = "wgpu")]
+pub fn wgpu_device(device: &str) -> WgpuDevice {
+    match split_device(device) {
+        (kind, _) if kind == "cpu" => WgpuDevice::Cpu,
+        (kind, _) if kind == "best" || kind == "default" => WgpuDevice::BestAvailable,
+        (kind, index) if kind == "discrete" => WgpuDevice::DiscreteGpu(index),
+        (kind, index) if kind == "integrated" => WgpuDevice::IntegratedGpu(index),
+        (kind, index) if kind == "virtual" => WgpuDevice::VirtualGpu(index),
+        _ => invalid_device(device),
+    }
+}
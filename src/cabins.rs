@@ -0,0 +1,88 @@
+use std::collections::{BTreeMap, HashMap};
+
+use burn::config::Config;
+
+use crate::dataset::{parse_cabin, CabinDeck, CabinSide, TitanicItemRaw};
+
+/// How cabin numbers are grouped into regions, and how far away a cabin still counts as a neighbour
+#[derive(Config, Debug)]
+pub struct CabinConfig {
+    /// Width of the cabin-number regions on decks that have no edges of their own
+    #[config(default = 300)]
+    pub region_size: u32,
+    /// Region edges of single decks, keyed by deck letter. A number equal to an edge goes in the lower region.
+    #[config(default = "BTreeMap::new()")]
+    pub region_edges: BTreeMap<String, Vec<u32>>,
+    /// How many cabin numbers either side of a cabin count as its neighbours
+    #[config(default = 1)]
+    pub neighbour_radius: u32,
+}
+
+impl CabinConfig {
+    /// The region of a cabin number on `deck` counting from 1, or 0 when the number isn't known
+    pub fn region(&self, deck: CabinDeck, number: Option<u32>) -> u32 {
+        let Some(number) = number else {
+            return 0;
+        };
+
+        match self.region_edges.get(&format!("{deck:?}")) {
+            Some(edges) => 1 + edges.iter().filter(|edge| number > **edge).count() as u32,
+            None => 1 + number / self.region_size.max(1),
+        }
+    }
+}
+
+/// The deck, number and side of a cabin, only when all three are known
+pub(crate) fn known_cabin(item: &TitanicItemRaw) -> Option<(CabinDeck, u32, CabinSide)> {
+    let cabin = item.cabin.as_deref()?;
+    if cabin.split('/').any(|part| part == "Unknown") {
+        return None;
+    }
+
+    parse_cabin(0, cabin).ok()
+}
+
+/// Passengers and cryo sleepers in each cabin, so the cabins around a passenger can be looked at
+#[derive(Debug, Clone, Default)]
+pub struct CabinNeighbours {
+    /// Keyed by deck and side, then by cabin number
+    cabins: HashMap<(CabinDeck, CabinSide), BTreeMap<u32, (u32, u32)>>,
+}
+
+impl CabinNeighbours {
+    pub fn new(rows: &[TitanicItemRaw]) -> Self {
+        let mut neighbours = Self::default();
+
+        for item in rows.iter() {
+            let Some((deck, number, side)) = known_cabin(item) else {
+                continue;
+            };
+
+            let cabin = neighbours
+                .cabins
+                .entry((deck, side))
+                .or_default()
+                .entry(number)
+                .or_default();
+            cabin.0 += 1;
+            cabin.1 += (item.cryo_sleep.as_deref() == Some("True")) as u32;
+        }
+
+        neighbours
+    }
+
+    /// Passengers and cryo sleepers in the cabins up to `radius` numbers away on the same deck and side, not
+    /// counting the cabin itself
+    pub fn around(&self, deck: CabinDeck, side: CabinSide, number: u32, radius: u32) -> (u32, u32) {
+        let Some(cabins) = self.cabins.get(&(deck, side)) else {
+            return (0, 0);
+        };
+
+        cabins
+            .range(number.saturating_sub(radius)..=number.saturating_add(radius))
+            .filter(|(other, _)| **other != number)
+            .fold((0, 0), |(passengers, cryo_sleep), (_, cabin)| {
+                (passengers + cabin.0, cryo_sleep + cabin.1)
+            })
+    }
+}
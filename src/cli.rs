@@ -0,0 +1,139 @@
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand};
+
+use crate::{
+    backend::BackendKind,
+    dataset::{BadRowPolicy, DataPaths},
+    predictions::PredictionsFormat,
+};
+
+#[derive(Parser, Debug)]
+#[command(
+    version,
+    about = "Train and run models for the Spaceship Titanic competition"
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Train a model and save it to the artifact directory
+    Train(TrainArgs),
+    /// Train a fresh model on every fold of the training data and report how well they generalize
+    CrossValidate(CrossValidateArgs),
+    /// Predict the submission set with a trained model
+    Infer(InferArgs),
+    /// Report the accuracy and loss of a trained model on the validation split
+    Evaluate(RunArgs),
+    /// Attribute every submission prediction of a trained model to its input features
+    Explain(ExplainArgs),
+    /// Report problems in the raw CSVs and print a short summary of the loaded datasets
+    #[command(visible_alias = "validate")]
+    InspectData(InspectArgs),
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct DataArgs {
+    /// CSV with the labelled training data
+    #[arg(long, default_value = "data/train.csv")]
+    pub train_path: PathBuf,
+    /// CSV with the unlabelled data to predict for the submission
+    #[arg(long, default_value = "data/test.csv")]
+    pub test_path: PathBuf,
+    /// What to do with rows holding values that can't be interpreted
+    #[arg(long, value_enum, default_value_t)]
+    pub bad_rows: BadRowPolicy,
+}
+
+impl DataArgs {
+    pub fn paths(&self) -> DataPaths {
+        DataPaths {
+            train: self.train_path.clone(),
+            test: self.test_path.clone(),
+        }
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct RunArgs {
+    /// Directory the config and model are saved to and loaded from
+    #[arg(long, default_value = "/tmp/titanic")]
+    pub artifact_dir: String,
+    #[command(flatten)]
+    pub data: DataArgs,
+    /// Backend used to run the model
+    #[arg(long, value_enum, default_value_t)]
+    pub backend: BackendKind,
+    /// Device for the backend, e.g. `cpu`, `cuda:0`, `discrete:0`
+    #[arg(long, default_value = "cpu")]
+    pub device: String,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct TrainArgs {
+    #[command(flatten)]
+    pub run: RunArgs,
+    /// Training config to use instead of the default one
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct InspectArgs {
+    #[command(flatten)]
+    pub data: DataArgs,
+    /// Training config whose split and imputation are inspected instead of the default one
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct InferArgs {
+    #[command(flatten)]
+    pub run: RunArgs,
+    /// Write P(Transported) for every passenger instead of the predicted label
+    #[arg(long)]
+    pub probabilities: bool,
+    /// Where the `PassengerId,Transported` CSV is written
+    #[arg(long, default_value = "submission.csv")]
+    pub output: PathBuf,
+    /// Sample submission the predictions must have the same passengers as, nothing is written when they don't
+    #[arg(long, default_value = "data/sample_submission.csv")]
+    pub sample_submission: PathBuf,
+    /// Also write probabilities, logits, labels and the threshold of every passenger to this file
+    #[arg(long)]
+    pub predictions: Option<PathBuf>,
+    /// Format of the predictions file
+    #[arg(long, value_enum, default_value_t)]
+    pub predictions_format: PredictionsFormat,
+    /// Model identifier written to the predictions file, defaults to the artifact directory
+    #[arg(long)]
+    pub model_id: Option<String>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct CrossValidateArgs {
+    #[command(flatten)]
+    pub run: RunArgs,
+    /// Training config to use instead of the default one
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+    /// Number of folds the training data is dealt into
+    #[arg(long, default_value_t = 5)]
+    pub folds: usize,
+    /// Save every fold's model to `<artifact-dir>/fold-<n>` so they can be used for inference or ensembling
+    #[arg(long)]
+    pub keep_models: bool,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ExplainArgs {
+    #[command(flatten)]
+    pub run: RunArgs,
+    /// Steps on the path from the baseline to the input that Integrated Gradients averages over
+    #[arg(long, default_value_t = 50)]
+    pub steps: usize,
+}
@@ -1,149 +1,61 @@
-use crate::dataset::{CabinSide, HomePlanet, TitanicItem};
+use crate::dataset::TitanicItem;
+use crate::features::{category_indices, FeatureSchema, CATEGORY_COUNTS};
+use crate::scaling::FittedScaler;
 
 use burn::{
     data::dataloader::batcher::Batcher,
-    tensor::{backend::Backend, ElementConversion, Int, Tensor},
+    tensor::{backend::Backend, Data, ElementConversion, Int, Shape, Tensor},
 };
 
 #[derive(Clone)]
 pub struct TitanicBatcher<B: Backend> {
     device: B::Device,
+    schema: FeatureSchema,
+    scaler: FittedScaler,
 }
 
 impl<B: Backend> TitanicBatcher<B> {
-    pub fn new(device: B::Device) -> Self {
-        Self { device }
+    pub fn new(device: B::Device, schema: FeatureSchema, scaler: FittedScaler) -> Self {
+        Self {
+            device,
+            schema,
+            scaler,
+        }
     }
 }
 
 #[derive(Clone, Debug)]
 pub struct TitanicBatch<B: Backend> {
     pub inputs: Tensor<B, 2>,
+    /// Indices of the categorical columns models with embedding tables look up, see `category_indices`
+    pub categories: Tensor<B, 2, Int>,
     pub targets: Tensor<B, 1, Int>,
+    /// `PassengerId` of every row, so predictions can be matched back to passengers
+    pub passenger_ids: Vec<String>,
 }
 
 impl<B: Backend> Batcher<TitanicItem, TitanicBatch<B>> for TitanicBatcher<B> {
     fn batch(&self, items: Vec<TitanicItem>) -> TitanicBatch<B> {
-        let mut inputs = Vec::new();
-
-        // pub group_number: u32,
-        // pub passenger_number: u8,
-        // pub home_planet: HomePlanet,
-        // pub cryo_sleep: bool,
-        // pub cabin_deck: CabinDeck,
-        // pub cabin_number: u32,
-        // pub cabin_side: CabinSide,
-        // pub desintation: DestinationPlanet,
-        // pub age: f32,
-        // pub vip: bool,
-        // pub room_service: f32,
-        // pub food_court: f32,
-        // pub shopping_mall: f32,
-        // pub spa: f32,
-        // pub vr_deck: f32,
-
-        // We should change some of these into one_hot encoding for categories
+        let num_features = self.schema.num_features();
+        let mut inputs = Vec::with_capacity(items.len() * num_features);
         for item in items.iter() {
-            let total_spending =
-                item.room_service + item.food_court + item.shopping_mall + item.spa + item.vr_deck;
-
-            let input_tensor = if total_spending > 0.0 {
-                Tensor::<B, 1>::from_floats(
-                    [
-                        item.group_number as f32,
-                        item.passenger_number as f32,
-                        item.cabin_number as f32,
-                        item.age,
-                        // Normalize the spending to a percentage of where the money is spent
-                        item.room_service / total_spending,
-                        item.food_court / total_spending,
-                        item.shopping_mall / total_spending,
-                        item.spa / total_spending,
-                        item.vr_deck / total_spending,
-                        // To call this feature engineering would be kind. Just add up the total spending of an individual
-                        total_spending,
-                    ],
-                    &self.device,
-                )
-            } else {
-                Tensor::<B, 1>::from_floats(
-                    [
-                        item.group_number as f32,
-                        item.passenger_number as f32,
-                        item.cabin_number as f32,
-                        item.age,
-                        // Normalize the spending to a percentage of where the money is spent
-                        item.room_service,
-                        item.food_court,
-                        item.shopping_mall,
-                        item.spa,
-                        item.vr_deck,
-                        // To call this feature engineering would be kind. Just add up the total spending of an individual
-                        total_spending,
-                    ],
-                    &self.device,
-                )
-            };
-
-            // let input_tensor = Tensor::<B, 1>::from_floats(
-            //     [
-            //         item.group_number as f32,
-            //         item.passenger_number as f32,
-            //         item.cabin_number as f32,
-            //         item.age,
-            //         item.room_service,
-            //         item.food_court,
-            //         item.shopping_mall,
-            //         item.spa,
-            //         item.vr_deck,
-            //     ],
-            //     &self.device,
-            // );
-
-            let spent_money = if total_spending == 0.0 {
-                Tensor::one_hot(0, 2, &self.device)
-            } else {
-                Tensor::one_hot(1, 2, &self.device)
-            };
-
-            let affluence = if item.age > 0.0 {
-                Tensor::<B, 1>::from_floats([total_spending / item.age], &self.device)
-            } else {
-                Tensor::<B, 1>::from_floats([0.0], &self.device)
-            };
-
-            let home_planet: Tensor<B, 1> =
-                Tensor::one_hot(item.home_planet as usize, 4, &self.device);
-            let cryo_sleep: Tensor<B, 1> =
-                Tensor::one_hot(if item.cryo_sleep { 1 } else { 0 }, 2, &self.device);
-            let cabin_deck: Tensor<B, 1> =
-                Tensor::one_hot(item.cabin_deck as usize, 11, &self.device);
-            let cabin_side: Tensor<B, 1> =
-                Tensor::one_hot(item.cabin_side as usize, 3, &self.device);
-            let destination: Tensor<B, 1> =
-                Tensor::one_hot(item.desintation as usize, 4, &self.device);
-            let vip: Tensor<B, 1> = Tensor::one_hot(if item.vip { 1 } else { 0 }, 2, &self.device);
-
-            let combination = Tensor::cat(
-                vec![
-                    input_tensor,
-                    affluence,
-                    spent_money,
-                    home_planet,
-                    cryo_sleep,
-                    cabin_deck,
-                    cabin_side,
-                    destination,
-                    vip,
-                ],
-                0,
-            );
-
-            inputs.push(combination.unsqueeze());
+            self.schema.encode(item, &mut inputs);
         }
+        self.scaler.apply(&mut inputs);
 
-        let inputs = Tensor::cat(inputs, 0);
-        // let inputs = self.min_max_norm(inputs);
+        let inputs = Tensor::from_floats(
+            Data::new(inputs, Shape::new([items.len(), num_features])),
+            &self.device,
+        );
+
+        let categories = items
+            .iter()
+            .flat_map(|item| category_indices(item).map(|index| index as i32))
+            .collect();
+        let categories = Tensor::from_ints(
+            Data::new(categories, Shape::new([items.len(), CATEGORY_COUNTS.len()])),
+            &self.device,
+        );
 
         let targets = items
             .iter()
@@ -154,6 +66,11 @@ impl<B: Backend> Batcher<TitanicItem, TitanicBatch<B>> for TitanicBatcher<B> {
 
         let targets = Tensor::cat(targets, 0);
 
-        TitanicBatch { inputs, targets }
+        TitanicBatch {
+            inputs,
+            categories,
+            targets,
+            passenger_ids: items.into_iter().map(|item| item.passenger_id).collect(),
+        }
     }
 }
@@ -1,15 +1,21 @@
-use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
 
-use burn::data::dataset::transform::Mapper;
-use burn::data::dataset::transform::MapperDataset;
 use burn::data::dataset::Dataset;
 use burn::data::dataset::InMemDataset;
-use rand::distributions::Distribution;
-use rand::distributions::Uniform;
-use rand::distributions::WeightedIndex;
-use rand::thread_rng;
+use clap::ValueEnum;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
 use serde::{Deserialize, Serialize};
 
+use crate::cabins::{known_cabin, CabinConfig, CabinNeighbours};
+use crate::error::DatasetError;
+use crate::groups::GroupStats;
+use crate::impute::{FittedImputation, ImputationConfig, ImputationReport};
+use crate::names::NameStats;
+use crate::split::SplitConfig;
+
 // PassengerId - A unique Id for each passenger. Each Id takes the form gggg_pp where gggg indicates a group the passenger is travelling with and pp is their number within the group. People in a group are often family members, but not always.
 // HomePlanet - The planet the passenger departed from, typically their planet of permanent residence.
 // CryoSleep - Indicates whether the passenger elected to be put into suspended animation for the duration of the voyage. Passengers in cryosleep are confined to their cabins.
@@ -21,23 +27,102 @@ use serde::{Deserialize, Serialize};
 // Name - The first and last names of the passenger.
 // Transported - Whether the passenger was transported to another dimension. This is the target, the column you are trying to predict.
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+/// Locations of the competition CSVs
+#[derive(Debug, Clone)]
+pub struct DataPaths {
+    /// Labelled data used for training and validation
+    pub train: PathBuf,
+    /// Unlabelled data the submission is made for
+    pub test: PathBuf,
+}
+
+/// A categorical column with a fixed set of values. The batcher one-hot encodes these with `COUNT` as the width, so
+/// the encoding always matches the enum.
+pub trait Category: Sized + Copy {
+    /// Every value, in the order of their one-hot index
+    const ALL: &'static [Self];
+    /// Number of values, which is the width of the one-hot encoding
+    const COUNT: usize = Self::ALL.len();
+
+    /// Position of the value in the one-hot encoding
+    fn index(self) -> usize;
+
+    /// Parses the value as written in the competition CSVs, `row` is only used for the error
+    fn parse(row: usize, value: &str) -> Result<Self, DatasetError>;
+}
+
+fn invalid_value(row: usize, column: &'static str, value: &str) -> DatasetError {
+    DatasetError::InvalidValue {
+        row,
+        column,
+        value: value.to_string(),
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum HomePlanet {
-    Earth,
+    Earth = 0,
     Mars,
     Europa,
     Unknown,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+impl Category for HomePlanet {
+    const ALL: &'static [Self] = &[
+        HomePlanet::Earth,
+        HomePlanet::Mars,
+        HomePlanet::Europa,
+        HomePlanet::Unknown,
+    ];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    fn parse(row: usize, value: &str) -> Result<Self, DatasetError> {
+        match value {
+            "Earth" => Ok(HomePlanet::Earth),
+            "Mars" => Ok(HomePlanet::Mars),
+            "Europa" => Ok(HomePlanet::Europa),
+            "Unknown" => Ok(HomePlanet::Unknown),
+            _ => Err(invalid_value(row, "HomePlanet", value)),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DestinationPlanet {
-    Trappist,
+    Trappist = 0,
     Pso,
     Cancri,
     Unknown,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+impl Category for DestinationPlanet {
+    const ALL: &'static [Self] = &[
+        DestinationPlanet::Trappist,
+        DestinationPlanet::Pso,
+        DestinationPlanet::Cancri,
+        DestinationPlanet::Unknown,
+    ];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    fn parse(row: usize, value: &str) -> Result<Self, DatasetError> {
+        match value {
+            "TRAPPIST-1e" => Ok(DestinationPlanet::Trappist),
+            "PSO J318.5-22" => Ok(DestinationPlanet::Pso),
+            "55 Cancri e" => Ok(DestinationPlanet::Cancri),
+            "Unknown" => Ok(DestinationPlanet::Unknown),
+            _ => Err(invalid_value(row, "Destination", value)),
+        }
+    }
+}
+
+/// The decks that actually occur in the data, `T` only has a handful of cabins
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum CabinDeck {
     A = 0,
     B,
@@ -46,29 +131,79 @@ pub enum CabinDeck {
     E,
     F,
     G,
-    H,
-    I,
-    J,
+    T,
     Unknown,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+impl Category for CabinDeck {
+    const ALL: &'static [Self] = &[
+        CabinDeck::A,
+        CabinDeck::B,
+        CabinDeck::C,
+        CabinDeck::D,
+        CabinDeck::E,
+        CabinDeck::F,
+        CabinDeck::G,
+        CabinDeck::T,
+        CabinDeck::Unknown,
+    ];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    fn parse(row: usize, value: &str) -> Result<Self, DatasetError> {
+        match value {
+            "A" => Ok(CabinDeck::A),
+            "B" => Ok(CabinDeck::B),
+            "C" => Ok(CabinDeck::C),
+            "D" => Ok(CabinDeck::D),
+            "E" => Ok(CabinDeck::E),
+            "F" => Ok(CabinDeck::F),
+            "G" => Ok(CabinDeck::G),
+            "T" => Ok(CabinDeck::T),
+            "Unknown" => Ok(CabinDeck::Unknown),
+            _ => Err(invalid_value(row, "Cabin", value)),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum CabinSide {
     Port = 0,
     Starboard = 1,
     Unknown,
 }
 
+impl Category for CabinSide {
+    const ALL: &'static [Self] = &[CabinSide::Port, CabinSide::Starboard, CabinSide::Unknown];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    fn parse(row: usize, value: &str) -> Result<Self, DatasetError> {
+        match value {
+            "P" => Ok(CabinSide::Port),
+            "S" => Ok(CabinSide::Starboard),
+            "Unknown" => Ok(CabinSide::Unknown),
+            _ => Err(invalid_value(row, "Cabin", value)),
+        }
+    }
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct TitanicItem {
+    /// `PassengerId` exactly as it appears in the CSV
+    pub passenger_id: String,
     pub group_number: u32,
     pub passenger_number: u32,
-    pub home_planet: u32,
+    pub home_planet: HomePlanet,
     pub cryo_sleep: bool,
-    pub cabin_deck: u32,
+    pub cabin_deck: CabinDeck,
     pub cabin_number: u32,
-    pub cabin_side: u32,
-    pub desintation: u32,
+    pub cabin_side: CabinSide,
+    pub destination: DestinationPlanet,
     pub age: f32,
     pub vip: bool,
     pub room_service: f32,
@@ -76,40 +211,64 @@ pub struct TitanicItem {
     pub shopping_mall: f32,
     pub spa: f32,
     pub vr_deck: f32,
-    // name: String,
+    /// Passengers of the same group with the same surname, including this one
+    pub family_size: u32,
+    /// Passengers with the same surname across train and test, including this one
+    pub surname_frequency: u32,
+    /// Whether anyone else in the group has the same surname
+    pub shares_surname: bool,
+    /// Passengers in the group, including this one
+    pub group_size: u32,
+    pub is_solo: bool,
+    /// Money spent by the whole group
+    pub group_spending: f32,
+    pub group_mean_spending: f32,
+    /// Share of the group that was in cryo sleep
+    pub group_cryo_fraction: f32,
+    /// Passengers sharing the cabin, including this one
+    pub cabin_size: u32,
+    /// Cabin-number region on the deck counting from 1, 0 when the number isn't known
+    pub cabin_region: u32,
+    /// Passengers in the neighbouring cabins on the same deck and side
+    pub neighbour_passengers: u32,
+    /// Cryo sleepers in the neighbouring cabins on the same deck and side
+    pub neighbour_cryo_sleepers: u32,
     pub transported: bool,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct TitanicItemRaw {
     #[serde(alias = "PassengerId")]
-    passenger_id: String,
+    pub(crate) passenger_id: String,
     #[serde(alias = "HomePlanet")]
-    home_planet: String,
+    pub(crate) home_planet: String,
     #[serde(alias = "CryoSleep")]
-    cryo_sleep: Option<String>,
+    pub(crate) cryo_sleep: Option<String>,
     #[serde(alias = "Cabin")]
-    cabin: Option<String>,
+    pub(crate) cabin: Option<String>,
     #[serde(alias = "Destination")]
-    destination: String,
+    pub(crate) destination: String,
     #[serde(alias = "Age")]
-    age: Option<f32>,
+    pub(crate) age: Option<f32>,
     #[serde(alias = "VIP")]
-    vip: Option<String>,
+    pub(crate) vip: Option<String>,
     #[serde(alias = "RoomService")]
-    room_service: Option<f32>,
+    pub(crate) room_service: Option<f32>,
     #[serde(alias = "FoodCourt")]
-    food_court: Option<f32>,
+    pub(crate) food_court: Option<f32>,
     #[serde(alias = "ShoppingMall")]
-    shopping_mall: Option<f32>,
+    pub(crate) shopping_mall: Option<f32>,
     #[serde(alias = "Spa")]
-    spa: Option<f32>,
+    pub(crate) spa: Option<f32>,
     #[serde(alias = "VRDeck")]
-    vr_deck: Option<f32>,
+    pub(crate) vr_deck: Option<f32>,
     #[serde(alias = "Name")]
-    name: Option<String>,
+    pub(crate) name: Option<String>,
     #[serde(alias = "Transported")]
-    transported: Option<String>,
+    pub(crate) transported: Option<String>,
+    /// Line in the CSV the passenger was read from, so errors point at the file even after splitting or skipping
+    #[serde(skip)]
+    pub(crate) row: usize,
 }
 
 // fn default_if_empty<'de, D, T>(de: D) -> Result<T, D::Error>
@@ -121,99 +280,254 @@ pub struct TitanicItemRaw {
 //     Option::<T>::deserialize(de).map(|x| x.unwrap_or_else(|| T::default()))
 // }
 
-struct RawToItem;
-
-impl Mapper<TitanicItemRaw, TitanicItem> for RawToItem {
-    fn map(&self, item: &TitanicItemRaw) -> TitanicItem {
-        let cabin = item
-            .cabin
-            .as_ref()
-            .unwrap()
-            .split('/')
-            .collect::<Vec<&str>>();
-
-        let cabin_deck = match cabin[0] {
-            "A" => 0,
-            "B" => 1,
-            "C" => 2,
-            "D" => 3,
-            "E" => 4,
-            "F" => 5,
-            "G" => 6,
-            "T" => 7,
-            "Unknown" => 8,
-            _ => panic!("Invalid cabin deck: {}", format!("{}", cabin[0])),
-        };
-        let cabin_side = match cabin[2] {
-            "P" => 0,
-            "S" => 1,
-            "Unknown" => 2,
-            _ => panic!("Invalid cabin side : {}", format!("{}", cabin[2])),
-        };
+/// What to do with a row holding a value we can't interpret
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BadRowPolicy {
+    /// Drop the row and keep loading
+    Skip,
+    /// Treat the bad value as missing so it gets filled in like any other gap, rows that can't be repaired are
+    /// dropped
+    Impute,
+    /// Stop loading and return the error
+    #[default]
+    Abort,
+}
 
-        let home_planet = match item.home_planet.as_str() {
-            "Earth" => 0,
-            "Mars" => 1,
-            "Europa" => 2,
-            "Unknown" => 3,
-            _ => panic!("Invalid home planet"),
-        };
+pub(crate) fn parse_bool(
+    row: usize,
+    column: &'static str,
+    value: &str,
+) -> Result<bool, DatasetError> {
+    match value {
+        "True" => Ok(true),
+        "False" => Ok(false),
+        _ => Err(DatasetError::InvalidValue {
+            row,
+            column,
+            value: value.to_string(),
+        }),
+    }
+}
 
-        let cryo_sleep = match item.cryo_sleep.clone().unwrap().as_str() {
-            "True" => true,
-            "False" => false,
-            _ => panic!("Invalid cryo sleep"),
-        };
+/// Line of the `index`-th record in its CSV counting from 1, with the header on line 1, so errors point at the line an
+/// editor shows. The reader's position wins when it has one, since quoted fields can span lines.
+pub(crate) fn csv_line(index: usize, position: Option<&csv::Position>) -> usize {
+    position.map_or(index + 2, |position| position.line() as usize)
+}
+
+/// Splits a `gggg_pp` passenger id into its group and passenger number
+pub(crate) fn parse_passenger_id(row: usize, value: &str) -> Result<(u32, u32), DatasetError> {
+    let invalid = || DatasetError::InvalidValue {
+        row,
+        column: "PassengerId",
+        value: value.to_string(),
+    };
+
+    let (group, passenger) = value.split_once('_').ok_or_else(invalid)?;
+    if group.len() != 4 || passenger.len() != 2 {
+        return Err(invalid());
+    }
+
+    Ok((
+        group.parse().map_err(|_| invalid())?,
+        passenger.parse().map_err(|_| invalid())?,
+    ))
+}
+
+/// Splits a `deck/num/side` cabin into its deck, number and side
+pub(crate) fn parse_cabin(
+    row: usize,
+    value: &str,
+) -> Result<(CabinDeck, u32, CabinSide), DatasetError> {
+    let invalid = || invalid_value(row, "Cabin", value);
+
+    let [deck, number, side] = value.split('/').collect::<Vec<&str>>()[..] else {
+        return Err(invalid());
+    };
+
+    let cabin_deck = CabinDeck::parse(row, deck).map_err(|_| invalid())?;
+    let cabin_number = match number {
+        "Unknown" => 0,
+        number => number.parse().map_err(|_| invalid())?,
+    };
+    let cabin_side = CabinSide::parse(row, side).map_err(|_| invalid())?;
+
+    Ok((cabin_deck, cabin_number, cabin_side))
+}
+
+/// Decides what happens to a value that failed to parse, returns true when it should be cleared and imputed
+fn clear_invalid<T>(
+    result: Result<T, DatasetError>,
+    policy: BadRowPolicy,
+) -> Result<bool, DatasetError> {
+    match result {
+        Ok(_) => Ok(false),
+        Err(err) if policy == BadRowPolicy::Impute => {
+            log::warn!("Imputing {err}");
+            Ok(true)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+impl TitanicItemRaw {
+    /// Checks every present value can be interpreted. With `BadRowPolicy::Impute` invalid values are cleared so
+    /// `fixup_dataset` fills them in, the id and label can't be imputed and always fail the row
+    fn check(&mut self, row: usize, policy: BadRowPolicy) -> Result<(), DatasetError> {
+        parse_passenger_id(row, &self.passenger_id)?;
+
+        if let Some(transported) = &self.transported {
+            parse_bool(row, "Transported", transported)?;
+        }
+
+        if !self.home_planet.is_empty()
+            && clear_invalid(HomePlanet::parse(row, &self.home_planet), policy)?
+        {
+            self.home_planet.clear();
+        }
+
+        if !self.destination.is_empty()
+            && clear_invalid(DestinationPlanet::parse(row, &self.destination), policy)?
+        {
+            self.destination.clear();
+        }
+
+        if let Some(cabin) = &self.cabin {
+            if clear_invalid(parse_cabin(row, cabin), policy)? {
+                self.cabin = None;
+            }
+        }
+
+        if let Some(cryo_sleep) = &self.cryo_sleep {
+            if clear_invalid(parse_bool(row, "CryoSleep", cryo_sleep), policy)? {
+                self.cryo_sleep = None;
+            }
+        }
+
+        if let Some(vip) = &self.vip {
+            if clear_invalid(parse_bool(row, "VIP", vip), policy)? {
+                self.vip = None;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl TitanicItemRaw {
+    pub(crate) fn group_number(&self) -> Option<u32> {
+        self.passenger_id.get(0..4)?.parse().ok()
+    }
+
+    /// The last word of the passenger's name
+    pub(crate) fn surname(&self) -> Option<&str> {
+        self.name.as_deref()?.split_whitespace().last()
+    }
+}
+
+/// Counts over every passenger in both competition CSVs, so every split sees whole groups and families whichever way
+/// the rows were dealt. They only look at features, never at labels.
+#[derive(Debug, Clone)]
+pub struct PassengerStats {
+    pub names: NameStats,
+    pub groups: GroupStats,
+    pub neighbours: CabinNeighbours,
+}
+
+impl PassengerStats {
+    pub fn new(rows: &[TitanicItemRaw]) -> Self {
+        Self {
+            names: NameStats::new(rows),
+            groups: GroupStats::new(rows),
+            neighbours: CabinNeighbours::new(rows),
+        }
+    }
+}
+
+struct RawToItem<'a> {
+    stats: &'a PassengerStats,
+    cabins: &'a CabinConfig,
+}
+
+impl RawToItem<'_> {
+    fn map(&self, item: &TitanicItemRaw) -> Result<TitanicItem, DatasetError> {
+        let row = item.row;
+        let missing = |column| DatasetError::MissingValue { row, column };
+
+        let (group_number, passenger_number) = parse_passenger_id(row, &item.passenger_id)?;
+        let (cabin_deck, cabin_number, cabin_side) =
+            parse_cabin(row, item.cabin.as_ref().ok_or_else(|| missing("Cabin"))?)?;
 
-        let destination_planet = match item.destination.as_str() {
-            "TRAPPIST-1e" => 0,
-            "PSO J318.5-22" => 1,
-            "55 Cancri e" => 2,
-            "Unknown" => 3,
-            _ => panic!("Invalid destination planet"),
+        let surname = item.surname().unwrap_or_default();
+        let family_size = match surname {
+            "" => 1,
+            surname => self.stats.names.family_size(group_number, surname) as u32,
         };
 
-        let vip = match item.vip.clone().unwrap().as_str() {
-            "True" => true,
-            "False" => false,
-            _ => panic!("Invalid vip"),
+        // The number is 0 when it isn't known, which is a real cabin number, so only known cabins get neighbours
+        let known_number = known_cabin(item).map(|_| cabin_number);
+        let (neighbour_passengers, neighbour_cryo_sleepers) = match known_number {
+            Some(number) => self.stats.neighbours.around(
+                cabin_deck,
+                cabin_side,
+                number,
+                self.cabins.neighbour_radius,
+            ),
+            None => (0, 0),
         };
 
+        let group_size = self.stats.groups.group_size(group_number);
+        let group_spending = self.stats.groups.group_spending(group_number);
+
         let transported = match &item.transported {
-            Some(val) => match val.as_str() {
-                "True" => true,
-                "False" => false,
-                _ => panic!("Invalid transported"),
-            },
+            Some(val) => parse_bool(row, "Transported", val)?,
             // This is the case for the submission dataset, since we are trying to infer those labels
             None => false,
         };
 
-        TitanicItem {
-            group_number: item.passenger_id[0..4].parse().unwrap(),
-            passenger_number: item.passenger_id[5..7].parse().unwrap(),
-            home_planet: home_planet,
-            cryo_sleep: cryo_sleep,
+        Ok(TitanicItem {
+            passenger_id: item.passenger_id.clone(),
+            group_number,
+            passenger_number,
+            home_planet: HomePlanet::parse(row, &item.home_planet)?,
+            cryo_sleep: parse_bool(
+                row,
+                "CryoSleep",
+                item.cryo_sleep
+                    .as_ref()
+                    .ok_or_else(|| missing("CryoSleep"))?,
+            )?,
             cabin_deck,
-            cabin_number: cabin[1].parse().unwrap_or_default(),
+            cabin_number,
             cabin_side,
-            desintation: destination_planet,
-            age: item.age.unwrap(),
-            vip: vip,
-            room_service: item.room_service.unwrap(),
-            food_court: item.food_court.unwrap(),
-            shopping_mall: item.shopping_mall.unwrap(),
-            spa: item.spa.unwrap(),
-            vr_deck: item.vr_deck.unwrap(),
-            transported: transported,
-        }
+            destination: DestinationPlanet::parse(row, &item.destination)?,
+            age: item.age.ok_or_else(|| missing("Age"))?,
+            vip: parse_bool(row, "VIP", item.vip.as_ref().ok_or_else(|| missing("VIP"))?)?,
+            room_service: item.room_service.ok_or_else(|| missing("RoomService"))?,
+            food_court: item.food_court.ok_or_else(|| missing("FoodCourt"))?,
+            shopping_mall: item.shopping_mall.ok_or_else(|| missing("ShoppingMall"))?,
+            spa: item.spa.ok_or_else(|| missing("Spa"))?,
+            vr_deck: item.vr_deck.ok_or_else(|| missing("VRDeck"))?,
+            family_size,
+            surname_frequency: self.stats.names.surname_frequency(surname) as u32,
+            shares_surname: family_size > 1,
+            group_size: group_size as u32,
+            is_solo: group_size == 1,
+            group_spending,
+            group_mean_spending: group_spending / group_size as f32,
+            group_cryo_fraction: self.stats.groups.cryo_fraction(group_number),
+            cabin_size: self.stats.groups.cabin_size(item) as u32,
+            cabin_region: self.cabins.region(cabin_deck, known_number),
+            neighbour_passengers,
+            neighbour_cryo_sleepers,
+            transported,
+        })
     }
 }
 
-type MappedDataset = MapperDataset<InMemDataset<TitanicItemRaw>, RawToItem, TitanicItemRaw>;
-
 pub struct TitanicDataset {
-    dataset: MappedDataset,
+    dataset: InMemDataset<TitanicItem>,
+    imputation: ImputationReport,
 }
 
 impl Dataset<TitanicItem> for TitanicDataset {
@@ -227,209 +541,394 @@ impl Dataset<TitanicItem> for TitanicDataset {
 }
 
 impl TitanicDataset {
-    fn build_family_db() -> HashMap<usize, (String, String)> {
-        let mut db = HashMap::new();
+    /// How many missing values were filled in while building the dataset
+    pub fn imputation_report(&self) -> &ImputationReport {
+        &self.imputation
+    }
 
-        csv::ReaderBuilder::new()
-            .delimiter(b',')
-            .terminator(csv::Terminator::CRLF)
-            .from_path("data/train.csv")
-            .unwrap()
-            .into_deserialize::<TitanicItemRaw>()
-            .for_each(|item| {
-                let item = item.unwrap();
-                let group_number = item.passenger_id[0..4].parse().unwrap();
-                if item.cabin.is_some() && !item.home_planet.is_empty() {
-                    db.insert(group_number, (item.cabin.unwrap(), item.home_planet));
+    /// Deserializes a single row. With `BadRowPolicy::Impute` fields that fail to deserialize are emptied one at a
+    /// time so they can be filled in later
+    fn parse_record(
+        row: usize,
+        headers: &csv::StringRecord,
+        mut record: csv::StringRecord,
+        policy: BadRowPolicy,
+    ) -> Result<TitanicItemRaw, DatasetError> {
+        loop {
+            let source = match record.deserialize::<TitanicItemRaw>(Some(headers)) {
+                Ok(item) => return Ok(item),
+                Err(source) => source,
+            };
+
+            let field = match source.kind() {
+                csv::ErrorKind::Deserialize { err, .. } if policy == BadRowPolicy::Impute => err
+                    .field()
+                    .map(|field| field as usize)
+                    // An empty field that still fails can't be fixed by emptying it
+                    .filter(|field| record.get(*field).is_some_and(|value| !value.is_empty())),
+                _ => None,
+            };
+
+            match field {
+                Some(field) => {
+                    log::warn!("Imputing row {row}: {source}");
+                    record = record
+                        .iter()
+                        .enumerate()
+                        .map(|(index, value)| if index == field { "" } else { value })
+                        .collect();
                 }
-            });
+                None => return Err(DatasetError::Csv { row, source }),
+            }
+        }
+    }
 
-        csv::ReaderBuilder::new()
+    /// Reads every row of a competition CSV from any reader, rows we can't interpret are handled according to
+    /// `policy`
+    pub fn read_records<R: Read>(
+        reader: R,
+        policy: BadRowPolicy,
+    ) -> Result<Vec<TitanicItemRaw>, DatasetError> {
+        let mut reader = csv::ReaderBuilder::new()
             .delimiter(b',')
             .terminator(csv::Terminator::CRLF)
-            .from_path("data/test.csv")
-            .unwrap()
-            .into_deserialize::<TitanicItemRaw>()
-            .for_each(|item| {
-                let item = item.unwrap();
-                let group_number = item.passenger_id[0..4].parse().unwrap();
-                if item.cabin.is_some() && !item.home_planet.is_empty() {
-                    db.insert(group_number, (item.cabin.unwrap(), item.home_planet));
-                }
-            });
+            .from_reader(reader);
+
+        let headers = reader
+            .headers()
+            .map_err(|source| DatasetError::Csv { row: 1, source })?
+            .clone();
+
+        let mut records = Vec::new();
+        for (index, record) in reader.records().enumerate() {
+            let row = match &record {
+                Ok(record) => csv_line(index, record.position()),
+                Err(err) => csv_line(index, err.position()),
+            };
+            let item = record
+                .map_err(|source| DatasetError::Csv { row, source })
+                .and_then(|record| Self::parse_record(row, &headers, record, policy))
+                .and_then(|mut item| item.check(row, policy).map(|_| item))
+                .map(|item| TitanicItemRaw { row, ..item });
+
+            match item {
+                Ok(item) => records.push(item),
+                Err(err) if policy == BadRowPolicy::Abort => return Err(err),
+                Err(err) => log::warn!("Skipping {err}"),
+            }
+        }
 
-        db
+        Ok(records)
     }
 
-    fn fixup_dataset(dataset: &mut Vec<TitanicItemRaw>) {
-        let family_db = Self::build_family_db();
+    /// Reads every row of the competition CSV at `path`
+    pub fn read_records_from_path<P: AsRef<Path>>(
+        path: P,
+        policy: BadRowPolicy,
+    ) -> Result<Vec<TitanicItemRaw>, DatasetError> {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|source| DatasetError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+        Self::read_records(BufReader::new(file), policy)
+    }
 
-        let mut rng = thread_rng();
+    fn fixup_dataset(
+        dataset: &mut [TitanicItemRaw],
+        imputation: &FittedImputation,
+        seed: u64,
+    ) -> ImputationReport {
+        imputation.transform(dataset, &mut StdRng::seed_from_u64(seed))
+    }
 
-        for item in dataset.iter_mut() {
-            if item.food_court.is_none() {
-                item.food_court = Some(0.0);
-            }
+    /// Fills in the missing values of every part with imputers fitted on the training split, each part drawing from
+    /// its own generator seeded from `seed` so the same seed always gives the same rows. The passenger counts are
+    /// taken over the filled in rows of all parts, so groups and cabins are aggregated from the values the model sees.
+    fn impute<const N: usize>(
+        parts: &mut [Vec<TitanicItemRaw>; N],
+        imputation: &FittedImputation,
+        seed: u64,
+    ) -> ([ImputationReport; N], PassengerStats) {
+        let reports = parts
+            .each_mut()
+            .map(|part| TitanicDataset::fixup_dataset(part, imputation, seed));
+        let stats = PassengerStats::new(&parts.concat());
+
+        (reports, stats)
+    }
 
-            if item.room_service.is_none() {
-                item.room_service = Some(0.0);
+    /// Builds a dataset from rows `impute` already filled in. Rows that still can't be mapped are handled according
+    /// to `policy`.
+    fn from_imputed(
+        records: &[TitanicItemRaw],
+        imputation: ImputationReport,
+        stats: &PassengerStats,
+        cabins: &CabinConfig,
+        policy: BadRowPolicy,
+    ) -> Result<Self, DatasetError> {
+        let to_item = RawToItem { stats, cabins };
+        let mut items = Vec::with_capacity(records.len());
+        for item in records.iter() {
+            match to_item.map(item) {
+                Ok(item) => items.push(item),
+                Err(err) if policy == BadRowPolicy::Abort => return Err(err),
+                Err(err) => log::warn!("Skipping {err}"),
             }
+        }
 
-            if item.shopping_mall.is_none() {
-                item.shopping_mall = Some(0.0);
-            }
+        Ok(Self {
+            dataset: InMemDataset::new(items),
+            imputation,
+        })
+    }
 
-            if item.spa.is_none() {
-                item.spa = Some(0.0);
-            }
+    /// Reads both competition CSVs, returning the training rows and the submission rows
+    fn read_all(
+        paths: &DataPaths,
+        policy: BadRowPolicy,
+    ) -> Result<(Vec<TitanicItemRaw>, Vec<TitanicItemRaw>), DatasetError> {
+        let train = Self::read_records_from_path(&paths.train, policy)?;
+        let test = Self::read_records_from_path(&paths.test, policy)?;
 
-            if item.vr_deck.is_none() {
-                item.vr_deck = Some(0.0);
-            }
+        Ok((train, test))
+    }
 
-            if item.cryo_sleep.is_none() {
-                let choices = ["True", "False"];
-                let dist = WeightedIndex::new([36, 64]).unwrap();
-                item.cryo_sleep = Some(choices[dist.sample(&mut rng)].to_string());
-            }
+    /// Reads both CSVs and fills them in the way `train_valid` did, returning the training split, the validation
+    /// split and the submission rows with the passenger counts over all of them
+    fn read_imputed(
+        paths: &DataPaths,
+        policy: BadRowPolicy,
+        split: &SplitConfig,
+        imputation: &FittedImputation,
+        seed: u64,
+    ) -> Result<
+        (
+            [Vec<TitanicItemRaw>; 3],
+            [ImputationReport; 3],
+            PassengerStats,
+        ),
+        DatasetError,
+    > {
+        let (records, test) = Self::read_all(paths, policy)?;
+        let (train, valid) = split.split(records, seed);
+        let mut parts = [train, valid, test];
+        let (reports, stats) = Self::impute(&mut parts, imputation, seed);
+
+        Ok((parts, reports, stats))
+    }
 
-            if item.cabin.is_none() {
-                if let Some(entry) = family_db.get(&item.passenger_id[0..4].parse().unwrap()) {
-                    item.cabin = Some(entry.0.clone())
-                }
-                item.cabin = Some("Unknown/Unknown/Unknown".to_string());
-            }
+    /// Reads the training CSV once and splits it into the training and validation datasets. The imputers are fitted
+    /// on the training split only and returned, so the same fitted state can be saved and applied to any other data.
+    pub fn train_valid(
+        paths: &DataPaths,
+        policy: BadRowPolicy,
+        split: &SplitConfig,
+        imputation: &ImputationConfig,
+        cabins: &CabinConfig,
+        seed: u64,
+    ) -> Result<(Self, Self, FittedImputation), DatasetError> {
+        let (records, test) = Self::read_all(paths, policy)?;
+        let (train, valid) = split.split(records, seed);
+        let imputation = imputation.fit(&train);
+
+        let mut parts = [train, valid, test];
+        let ([train_report, valid_report, _], stats) = Self::impute(&mut parts, &imputation, seed);
+        let [train, valid, _] = &parts;
+
+        let train = Self::from_imputed(train, train_report, &stats, cabins, policy)?;
+        let valid = Self::from_imputed(valid, valid_report, &stats, cabins, policy)?;
+
+        Ok((train, valid, imputation))
+    }
 
-            if item.home_planet.is_empty() {
-                if let Some(entry) = family_db.get(&item.passenger_id[0..4].parse().unwrap()) {
-                    item.home_planet = entry.1.clone()
-                }
-                item.home_planet = "Unknown".to_string();
-            }
+    /// Reads the training CSV once and deals it into `k` folds, returning the training and validation datasets of
+    /// every fold with the imputers fitted on that fold's training rows
+    pub fn folds(
+        paths: &DataPaths,
+        policy: BadRowPolicy,
+        split: &SplitConfig,
+        imputation: &ImputationConfig,
+        cabins: &CabinConfig,
+        k: usize,
+        seed: u64,
+    ) -> Result<Vec<(Self, Self, FittedImputation)>, DatasetError> {
+        let (records, test) = Self::read_all(paths, policy)?;
+
+        split
+            .folds(&records, k, seed)
+            .into_iter()
+            .map(|(train, valid)| {
+                let imputation = imputation.fit(&train);
+
+                let mut parts = [train, valid, test.clone()];
+                let ([train_report, valid_report, _], stats) =
+                    Self::impute(&mut parts, &imputation, seed);
+                let [train, valid, _] = &parts;
+
+                let train = Self::from_imputed(train, train_report, &stats, cabins, policy)?;
+                let valid = Self::from_imputed(valid, valid_report, &stats, cabins, policy)?;
+                Ok((train, valid, imputation))
+            })
+            .collect()
+    }
 
-            // Below this, I am not sure there is any way to fix the data, so we will just set it to a default value
-            // VIP doesn't seem to be shared in families
+    /// Rebuilds the validation dataset `train_valid` gave at training time from the saved imputers
+    pub fn valid(
+        paths: &DataPaths,
+        policy: BadRowPolicy,
+        split: &SplitConfig,
+        imputation: &FittedImputation,
+        cabins: &CabinConfig,
+        seed: u64,
+    ) -> Result<Self, DatasetError> {
+        let ([_, valid, _], [_, report, _], stats) =
+            Self::read_imputed(paths, policy, split, imputation, seed)?;
+
+        Self::from_imputed(&valid, report, &stats, cabins, policy)
+    }
 
-            if item.age.is_none() {
-                item.age = Some(Uniform::new(15.0, 47.0).sample(&mut rng));
-            }
+    /// Builds the submission dataset from the saved imputers, with the passenger counts the model was trained on
+    pub fn submission(
+        paths: &DataPaths,
+        policy: BadRowPolicy,
+        split: &SplitConfig,
+        imputation: &FittedImputation,
+        cabins: &CabinConfig,
+        seed: u64,
+    ) -> Result<Self, DatasetError> {
+        let ([_, _, test], [_, _, report], stats) =
+            Self::read_imputed(paths, policy, split, imputation, seed)?;
+
+        Self::from_imputed(&test, report, &stats, cabins, policy)
+    }
+}
 
-            if item.vip.is_none() {
-                item.vip = Some("False".to_string());
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::impute::ImputeStrategy;
 
-            if item.destination.is_empty() {
-                item.destination = "Unknown".to_string();
-            }
-        }
+    const HEADER: &str =
+        "PassengerId,HomePlanet,CryoSleep,Cabin,Destination,Age,VIP,RoomService,FoodCourt,\
+ShoppingMall,Spa,VRDeck,Name,Transported";
+
+    fn load(rows: &[&str], policy: BadRowPolicy) -> Result<TitanicDataset, DatasetError> {
+        load_with(rows, policy, &ImputationConfig::new())
     }
 
-    pub fn train() -> Self {
-        let mut data = csv::ReaderBuilder::new()
-            .delimiter(b',')
-            .terminator(csv::Terminator::CRLF)
-            .from_path("data/train.csv")
-            .unwrap()
-            .into_deserialize::<TitanicItemRaw>()
-            .map(|res| res.unwrap())
-            // Filter out garbage data here, for the time being, we are just saying any entry that doesn't have an age is garbage
-            // .filter(|item| {
-            //     item.age.is_some()
-            //         && item.food_court.is_some()
-            //         && item.room_service.is_some()
-            //         && item.shopping_mall.is_some()
-            //         && item.spa.is_some()
-            //         && item.vr_deck.is_some()
-            //         && item.cryo_sleep.is_some()
-            //         && item.vip.is_some()
-            //         && item.cabin.is_some()
-            // })
-            .collect();
-
-        TitanicDataset::fixup_dataset(&mut data);
-
-        let dataset = InMemDataset::new(data);
-
-        let dataset_length = dataset.len();
-
-        let train_dataset = InMemDataset::new(
-            dataset
-                .iter()
-                .take((dataset_length as f32 * (9. / 10.)).round() as usize)
-                .collect(),
-        );
-
-        let dataset = MapperDataset::new(train_dataset, RawToItem);
-        Self { dataset }
-    }
-
-    // We don't actually have a test dataset for validation, so we need to make our own, we will just keep 10% of the training set to validate
-    pub fn test() -> Self {
-        let mut data = csv::ReaderBuilder::new()
-            .delimiter(b',')
-            .terminator(csv::Terminator::CRLF)
-            .from_path("data/train.csv")
-            .unwrap()
-            .into_deserialize::<TitanicItemRaw>()
-            .map(|res| res.unwrap())
-            // Filter out garbage data here, for the time being, we are just saying any entry that doesn't have an age is garbage
-            // .filter(|item| {
-            //     item.age.is_some()
-            //         && item.food_court.is_some()
-            //         && item.room_service.is_some()
-            //         && item.shopping_mall.is_some()
-            //         && item.spa.is_some()
-            //         && item.vr_deck.is_some()
-            //         && item.cryo_sleep.is_some()
-            //         && item.vip.is_some()
-            //         && item.cabin.is_some()
-            // })
-            .collect();
-
-        TitanicDataset::fixup_dataset(&mut data);
-
-        let dataset = InMemDataset::new(data);
-
-        let dataset_length = dataset.len();
-
-        let test_dataset = InMemDataset::new(
-            dataset
-                .iter()
-                .skip((dataset_length as f32 * (9. / 10.)).round() as usize)
-                .collect(),
-        );
-
-        let dataset = MapperDataset::new(test_dataset, RawToItem);
-        Self { dataset }
-    }
-
-    pub fn submission() -> Self {
-        let mut data = csv::ReaderBuilder::new()
-            .delimiter(b',')
-            .terminator(csv::Terminator::CRLF)
-            .from_path("data/test.csv")
-            .unwrap()
-            .into_deserialize::<TitanicItemRaw>()
-            .map(|res| res.unwrap())
-            // Filter out garbage data here, for the time being, we are just saying any entry that doesn't have an age is garbage
-            // .filter(|item| {
-            //     item.age.is_some()
-            //         && item.food_court.is_some()
-            //         && item.room_service.is_some()
-            //         && item.shopping_mall.is_some()
-            //         && item.spa.is_some()
-            //         && item.vr_deck.is_some()
-            //         && item.cryo_sleep.is_some()
-            //         && item.vip.is_some()
-            //         && item.cabin.is_some()
-            // })
-            .collect();
-
-        TitanicDataset::fixup_dataset(&mut data);
-
-        let dataset = InMemDataset::new(data);
-
-        let dataset = MapperDataset::new(dataset, RawToItem);
-        Self { dataset }
+    fn load_with(
+        rows: &[&str],
+        policy: BadRowPolicy,
+        imputation: &ImputationConfig,
+    ) -> Result<TitanicDataset, DatasetError> {
+        let csv = [HEADER]
+            .iter()
+            .chain(rows.iter())
+            .copied()
+            .collect::<Vec<_>>()
+            .join("\n");
+        let records = TitanicDataset::read_records(csv.as_bytes(), policy)?;
+        let imputation = imputation.fit(&records);
+        let mut parts = [records];
+        let ([report], stats) = TitanicDataset::impute(&mut parts, &imputation, 42);
+
+        TitanicDataset::from_imputed(&parts[0], report, &stats, &CabinConfig::new(), policy)
+    }
+
+    #[test]
+    fn reads_items_from_an_in_memory_csv() {
+        let dataset = load(
+            &[
+                "0001_01,Europa,False,B/0/P,TRAPPIST-1e,39.0,False,0.0,0.0,0.0,0.0,0.0,Maham Ofracculy,False",
+                "0002_01,Earth,False,F/0/S,55 Cancri e,24.0,False,109.0,9.0,25.0,549.0,44.0,Juanna Vines,True",
+            ],
+            BadRowPolicy::Abort,
+        )
+        .unwrap();
+
+        assert_eq!(dataset.len(), 2);
+        let item = dataset.get(1).unwrap();
+        assert_eq!(item.passenger_id, "0002_01");
+        assert_eq!((item.group_number, item.passenger_number), (2, 1));
+        assert_eq!(item.home_planet, HomePlanet::Earth);
+        assert_eq!(item.destination, DestinationPlanet::Cancri);
+        assert_eq!(item.cabin_deck, CabinDeck::F);
+        assert_eq!(item.cabin_side, CabinSide::Starboard);
+        assert_eq!(item.spa, 549.0);
+        assert!(item.transported);
+    }
+
+    #[test]
+    fn bad_rows_follow_the_policy() {
+        let rows = [
+            "0001_01,Europa,False,B/0/P,TRAPPIST-1e,39.0,False,0.0,0.0,0.0,0.0,0.0,Maham Ofracculy,False",
+            "0002_01,Venus,False,F/0/S,TRAPPIST-1e,24.0,False,0.0,0.0,0.0,0.0,0.0,Juanna Vines,True",
+        ];
+
+        // The header is line 1, so the second passenger is on line 3
+        assert!(matches!(
+            load(&rows, BadRowPolicy::Abort),
+            Err(DatasetError::InvalidValue {
+                row: 3,
+                column: "HomePlanet",
+                ..
+            })
+        ));
+        assert_eq!(load(&rows, BadRowPolicy::Skip).unwrap().len(), 1);
+
+        let dataset = load(&rows, BadRowPolicy::Impute).unwrap();
+        assert_eq!(dataset.len(), 2);
+        assert_eq!(dataset.get(1).unwrap().home_planet, HomePlanet::Unknown);
+    }
+
+    #[test]
+    fn impute_policy_fills_bad_values_and_drops_bad_labels() {
+        let dataset = load(
+            &[
+                "0001_01,Europa,False,B/0/P,TRAPPIST-1e,abc,False,0.0,0.0,0.0,0.0,0.0,Maham Ofracculy,False",
+                "0002_01,Earth,False,F/0/S,TRAPPIST-1e,24.0,False,0.0,0.0,0.0,0.0,0.0,Juanna Vines,Maybe",
+                "0003_01,Earth,False,F/1/S,TRAPPIST-1e,31.0,False,0.0,0.0,0.0,0.0,0.0,Solam Susent,True",
+            ],
+            BadRowPolicy::Impute,
+        )
+        .unwrap();
+
+        // The label can't be imputed, so only the passenger with the unreadable age is kept and gets a new one
+        assert_eq!(dataset.len(), 2);
+        let item = dataset.get(0).unwrap();
+        assert_eq!(item.passenger_id, "0001_01");
+        assert!((15.0..=47.0).contains(&item.age));
+        assert_eq!(dataset.get(1).unwrap().passenger_id, "0003_01");
+    }
+
+    #[test]
+    fn group_aggregates_count_imputed_values() {
+        let imputation = ImputationConfig::new()
+            .with_cryo_sleep(ImputeStrategy::Constant {
+                value: "True".to_string(),
+            })
+            .with_spa(ImputeStrategy::Constant {
+                value: "100".to_string(),
+            });
+        let dataset = load_with(
+            &[
+                "0001_01,Europa,,B/0/P,TRAPPIST-1e,39.0,False,0.0,0.0,0.0,0.0,0.0,Maham Ofracculy,False",
+                "0002_01,Earth,False,F/0/S,TRAPPIST-1e,24.0,False,0.0,0.0,0.0,,0.0,Juanna Vines,True",
+                "0002_02,Earth,False,F/0/S,TRAPPIST-1e,30.0,False,0.0,0.0,0.0,50.0,0.0,Altark Vines,True",
+            ],
+            BadRowPolicy::Abort,
+            &imputation,
+        )
+        .unwrap();
+
+        let solo = dataset.get(0).unwrap();
+        assert!(solo.cryo_sleep);
+        assert_eq!(solo.group_cryo_fraction, 1.0);
+
+        let pair = dataset.get(1).unwrap();
+        assert_eq!(pair.spa, 100.0);
+        assert_eq!(pair.group_spending, 150.0);
     }
 }
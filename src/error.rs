@@ -0,0 +1,116 @@
+use std::{fmt, path::PathBuf};
+
+/// Errors raised while loading a competition CSV into a dataset. `row` is the line in the file counting from 1, with
+/// the header on line 1.
+#[derive(Debug)]
+pub enum DatasetError {
+    /// The file could not be opened
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    /// The row could not be read or deserialized by the CSV reader
+    Csv { row: usize, source: csv::Error },
+    /// A column holds a value we don't know how to interpret
+    InvalidValue {
+        row: usize,
+        column: &'static str,
+        value: String,
+    },
+    /// A column is empty and nothing filled it in
+    MissingValue { row: usize, column: &'static str },
+}
+
+impl fmt::Display for DatasetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DatasetError::Io { path, source } => {
+                write!(f, "failed to open {}: {source}", path.display())
+            }
+            DatasetError::Csv { row, source } => write!(f, "line {row}: {source}"),
+            DatasetError::InvalidValue { row, column, value } => {
+                write!(f, "line {row}: invalid value {value:?} in column {column}")
+            }
+            DatasetError::MissingValue { row, column } => {
+                write!(f, "line {row}: missing value in column {column}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for DatasetError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DatasetError::Io { source, .. } => Some(source),
+            DatasetError::Csv { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Errors raised while writing the submission or predictions files, or checking the submission against the sample
+/// submission
+#[derive(Debug)]
+pub enum SubmissionError {
+    /// The file could not be created or opened
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    /// The file could not be written or read as CSV
+    Csv { path: PathBuf, source: csv::Error },
+    /// A JSON Lines row could not be written
+    Json {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    /// The file has no `PassengerId` column
+    MissingIdColumn { path: PathBuf },
+    /// The submission has a different number of rows than the sample submission
+    RowCount { expected: usize, found: usize },
+    /// Passengers of the sample submission that have no prediction, and predicted passengers the sample doesn't have
+    PassengerIds {
+        missing: Vec<String>,
+        unexpected: Vec<String>,
+    },
+}
+
+impl fmt::Display for SubmissionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SubmissionError::Io { path, source } => {
+                write!(f, "failed to open {}: {source}", path.display())
+            }
+            SubmissionError::Csv { path, source } => write!(f, "{}: {source}", path.display()),
+            SubmissionError::Json { path, source } => write!(f, "{}: {source}", path.display()),
+            SubmissionError::MissingIdColumn { path } => {
+                write!(f, "{} has no PassengerId column", path.display())
+            }
+            SubmissionError::RowCount { expected, found } => {
+                write!(f, "expected {expected} rows like the sample submission, found {found}")
+            }
+            SubmissionError::PassengerIds {
+                missing,
+                unexpected,
+            } => write!(
+                f,
+                "{} passengers of the sample submission are missing (first {:?}), {} are not in it (first {:?})",
+                missing.len(),
+                missing.first(),
+                unexpected.len(),
+                unexpected.first()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SubmissionError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SubmissionError::Io { source, .. } => Some(source),
+            SubmissionError::Csv { source, .. } => Some(source),
+            SubmissionError::Json { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
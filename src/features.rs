@@ -0,0 +1,442 @@
+use std::collections::HashMap;
+
+use burn::config::Config;
+use serde::{Deserialize, Serialize};
+
+use crate::dataset::{CabinDeck, CabinSide, Category, DestinationPlanet, HomePlanet, TitanicItem};
+
+/// Cabin-number regions the model tells apart, region 0 is for unknown numbers and later regions share the last slot
+pub const CABIN_REGIONS: usize = 8;
+
+/// Number of categories of every column in `category_indices`, in the same order
+pub const CATEGORY_COUNTS: [usize; 5] = [
+    HomePlanet::COUNT,
+    DestinationPlanet::COUNT,
+    CabinDeck::COUNT,
+    CabinSide::COUNT,
+    CABIN_REGIONS,
+];
+
+/// The categorical columns that models with embedding tables look up: home planet, destination, deck, side and
+/// cabin-number region
+pub fn category_indices(item: &TitanicItem) -> [usize; 5] {
+    [
+        item.home_planet.index(),
+        item.destination.index(),
+        item.cabin_deck.index(),
+        item.cabin_side.index(),
+        (item.cabin_region as usize).min(CABIN_REGIONS - 1),
+    ]
+}
+
+/// How a feature is laid out in the model input
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FeatureKind {
+    /// A single number
+    Numeric,
+    /// A one-hot encoding with one column per category
+    OneHot,
+}
+
+/// One engineered feature of the model input
+#[derive(Config, Debug, PartialEq)]
+pub struct Feature {
+    pub name: String,
+    pub kind: FeatureKind,
+    /// Number of input columns the feature takes up
+    pub width: usize,
+}
+
+/// The value of a column or feature for one passenger
+#[derive(Clone, Copy, Debug)]
+enum FeatureValue {
+    Number(f32),
+    Category { index: usize, count: usize },
+}
+
+impl FeatureValue {
+    fn flag(value: bool) -> Self {
+        FeatureValue::Category {
+            index: value as usize,
+            count: 2,
+        }
+    }
+
+    fn category<C: Category>(value: C) -> Self {
+        FeatureValue::Category {
+            index: value.index(),
+            count: C::COUNT,
+        }
+    }
+}
+
+/// The columns of a `TitanicItem` transforms can read, spelled like the CSV headers. Built for every item of every
+/// batch, so the names stay static.
+fn base_columns(item: &TitanicItem) -> HashMap<&'static str, FeatureValue> {
+    [
+        (
+            "GroupNumber",
+            FeatureValue::Number(item.group_number as f32),
+        ),
+        (
+            "PassengerNumber",
+            FeatureValue::Number(item.passenger_number as f32),
+        ),
+        ("HomePlanet", FeatureValue::category(item.home_planet)),
+        ("CryoSleep", FeatureValue::flag(item.cryo_sleep)),
+        ("CabinDeck", FeatureValue::category(item.cabin_deck)),
+        (
+            "CabinNumber",
+            FeatureValue::Number(item.cabin_number as f32),
+        ),
+        ("CabinSide", FeatureValue::category(item.cabin_side)),
+        ("Destination", FeatureValue::category(item.destination)),
+        ("Age", FeatureValue::Number(item.age)),
+        ("VIP", FeatureValue::flag(item.vip)),
+        ("RoomService", FeatureValue::Number(item.room_service)),
+        ("FoodCourt", FeatureValue::Number(item.food_court)),
+        ("ShoppingMall", FeatureValue::Number(item.shopping_mall)),
+        ("Spa", FeatureValue::Number(item.spa)),
+        ("VRDeck", FeatureValue::Number(item.vr_deck)),
+        ("FamilySize", FeatureValue::Number(item.family_size as f32)),
+        (
+            "SurnameFrequency",
+            FeatureValue::Number(item.surname_frequency as f32),
+        ),
+        ("SharesSurname", FeatureValue::flag(item.shares_surname)),
+        ("GroupSize", FeatureValue::Number(item.group_size as f32)),
+        ("IsSolo", FeatureValue::flag(item.is_solo)),
+        ("GroupSpending", FeatureValue::Number(item.group_spending)),
+        (
+            "GroupMeanSpending",
+            FeatureValue::Number(item.group_mean_spending),
+        ),
+        (
+            "GroupCryoFraction",
+            FeatureValue::Number(item.group_cryo_fraction),
+        ),
+        ("CabinSize", FeatureValue::Number(item.cabin_size as f32)),
+        (
+            "CabinRegion",
+            FeatureValue::Number(item.cabin_region as f32),
+        ),
+        (
+            "CabinDeckSide",
+            FeatureValue::Category {
+                index: item.cabin_deck.index() * CabinSide::COUNT + item.cabin_side.index(),
+                count: CabinDeck::COUNT * CabinSide::COUNT,
+            },
+        ),
+        (
+            "NeighbourPassengers",
+            FeatureValue::Number(item.neighbour_passengers as f32),
+        ),
+        (
+            "NeighbourCryoSleepers",
+            FeatureValue::Number(item.neighbour_cryo_sleepers as f32),
+        ),
+    ]
+    .into_iter()
+    .collect()
+}
+
+/// A placeholder passenger, only used to work out the shape of every feature without any data
+fn shape_item() -> TitanicItem {
+    TitanicItem {
+        passenger_id: String::new(),
+        group_number: 0,
+        passenger_number: 0,
+        home_planet: HomePlanet::Unknown,
+        cryo_sleep: false,
+        cabin_deck: CabinDeck::Unknown,
+        cabin_number: 0,
+        cabin_side: CabinSide::Unknown,
+        destination: DestinationPlanet::Unknown,
+        age: 0.0,
+        vip: false,
+        room_service: 0.0,
+        food_court: 0.0,
+        shopping_mall: 0.0,
+        spa: 0.0,
+        vr_deck: 0.0,
+        family_size: 1,
+        surname_frequency: 1,
+        shares_surname: false,
+        group_size: 1,
+        is_solo: true,
+        group_spending: 0.0,
+        group_mean_spending: 0.0,
+        group_cryo_fraction: 0.0,
+        cabin_size: 1,
+        cabin_region: 0,
+        neighbour_passengers: 0,
+        neighbour_cryo_sleepers: 0,
+        transported: false,
+    }
+}
+
+/// One step of the feature pipeline. Every transform reads base columns or features made by earlier transforms, and
+/// its result can be read by the transforms after it.
+#[derive(Config, Debug, PartialEq)]
+pub enum FeatureTransform {
+    /// A base column as it is, numbers stay numbers and categories are one-hot encoded
+    Column { column: String },
+    /// The sum of numeric columns
+    Sum { name: String, columns: Vec<String> },
+    /// `numerator / denominator`, or `fallback` when the denominator is zero
+    Ratio {
+        name: String,
+        numerator: String,
+        denominator: String,
+        fallback: f32,
+    },
+    /// `ln(1 + x)` of a numeric column, negative values count as zero
+    Log { name: String, column: String },
+    /// Which of the bins split at `edges` a number falls in, one-hot encoded. A value equal to an edge goes in the
+    /// lower bin.
+    Bin {
+        name: String,
+        column: String,
+        edges: Vec<f32>,
+    },
+    /// The product of two numbers, or every combination of two categories
+    Interaction {
+        name: String,
+        left: String,
+        right: String,
+    },
+    /// A whole number one-hot encoded into `size` categories, larger numbers go in the last one
+    OneHot {
+        name: String,
+        column: String,
+        size: usize,
+    },
+}
+
+impl FeatureTransform {
+    pub fn name(&self) -> &str {
+        match self {
+            FeatureTransform::Column { column } => column,
+            FeatureTransform::Sum { name, .. }
+            | FeatureTransform::Ratio { name, .. }
+            | FeatureTransform::Log { name, .. }
+            | FeatureTransform::Bin { name, .. }
+            | FeatureTransform::Interaction { name, .. }
+            | FeatureTransform::OneHot { name, .. } => name,
+        }
+    }
+
+    fn apply(&self, values: &HashMap<&str, FeatureValue>) -> FeatureValue {
+        let value = |column: &str| {
+            *values.get(column).unwrap_or_else(|| {
+                panic!(
+                    "{} reads {column:?}, which isn't a column or an earlier feature",
+                    self.name()
+                )
+            })
+        };
+        let number = |column: &str| match value(column) {
+            FeatureValue::Number(number) => number,
+            FeatureValue::Category { .. } => {
+                panic!("{} needs {column:?} to be a number", self.name())
+            }
+        };
+
+        match self {
+            FeatureTransform::Column { column } => value(column),
+            FeatureTransform::Sum { columns, .. } => {
+                FeatureValue::Number(columns.iter().map(|column| number(column)).sum())
+            }
+            FeatureTransform::Ratio {
+                numerator,
+                denominator,
+                fallback,
+                ..
+            } => match number(denominator) {
+                denominator if denominator == 0.0 => FeatureValue::Number(*fallback),
+                denominator => FeatureValue::Number(number(numerator) / denominator),
+            },
+            FeatureTransform::Log { column, .. } => {
+                FeatureValue::Number(number(column).max(0.0).ln_1p())
+            }
+            FeatureTransform::Bin { column, edges, .. } => {
+                let value = number(column);
+                FeatureValue::Category {
+                    index: edges.iter().filter(|edge| value > **edge).count(),
+                    count: edges.len() + 1,
+                }
+            }
+            FeatureTransform::Interaction { left, right, .. } => {
+                match (value(left), value(right)) {
+                    (FeatureValue::Number(left), FeatureValue::Number(right)) => {
+                        FeatureValue::Number(left * right)
+                    }
+                    (
+                        FeatureValue::Category {
+                            index: left,
+                            count: left_count,
+                        },
+                        FeatureValue::Category {
+                            index: right,
+                            count: right_count,
+                        },
+                    ) => FeatureValue::Category {
+                        index: left * right_count + right,
+                        count: left_count * right_count,
+                    },
+                    _ => panic!(
+                        "{} needs {left:?} and {right:?} to both be numbers or both be categories",
+                        self.name()
+                    ),
+                }
+            }
+            FeatureTransform::OneHot { column, size, .. } => FeatureValue::Category {
+                index: (number(column).max(0.0) as usize).min(size.saturating_sub(1)),
+                count: *size,
+            },
+        }
+    }
+}
+
+/// The feature engineering, configured from `TrainingConfig` so a new feature set needs no code changes
+#[derive(Config, Debug, PartialEq)]
+pub struct FeaturePipeline {
+    /// Every transform in the order it runs, which is also the order its feature is written to the model input
+    pub transforms: Vec<FeatureTransform>,
+    /// Features other transforms read that shouldn't go into the model input themselves
+    #[config(default = "Vec::new()")]
+    pub hidden: Vec<String>,
+}
+
+impl FeaturePipeline {
+    /// The features we currently train on
+    pub fn titanic() -> Self {
+        let column = |column: &str| FeatureTransform::Column {
+            column: column.to_string(),
+        };
+        // Normalize the spending to a percentage of where the money is spent
+        let share = |name: &str, column: &str| FeatureTransform::Ratio {
+            name: name.to_string(),
+            numerator: column.to_string(),
+            denominator: "TotalSpending".to_string(),
+            fallback: 0.0,
+        };
+
+        Self::new(vec![
+            column("GroupSize"),
+            column("IsSolo"),
+            column("GroupSpending"),
+            column("GroupMeanSpending"),
+            column("GroupCryoFraction"),
+            column("CabinSize"),
+            column("FamilySize"),
+            column("SurnameFrequency"),
+            column("SharesSurname"),
+            // Regions of 300 cabins cover every deck, plus region 0 for unknown numbers
+            FeatureTransform::OneHot {
+                name: "CabinRegionOneHot".to_string(),
+                column: "CabinRegion".to_string(),
+                size: CABIN_REGIONS,
+            },
+            column("NeighbourCryoSleepers"),
+            column("Age"),
+            // To call this feature engineering would be kind. Just add up the total spending of an individual
+            FeatureTransform::Sum {
+                name: "TotalSpending".to_string(),
+                columns: ["RoomService", "FoodCourt", "ShoppingMall", "Spa", "VRDeck"]
+                    .map(str::to_string)
+                    .to_vec(),
+            },
+            share("RoomServiceShare", "RoomService"),
+            share("FoodCourtShare", "FoodCourt"),
+            share("ShoppingMallShare", "ShoppingMall"),
+            share("SpaShare", "Spa"),
+            share("VRDeckShare", "VRDeck"),
+            FeatureTransform::Ratio {
+                name: "Affluence".to_string(),
+                numerator: "TotalSpending".to_string(),
+                denominator: "Age".to_string(),
+                fallback: 0.0,
+            },
+            FeatureTransform::Bin {
+                name: "SpentMoney".to_string(),
+                column: "TotalSpending".to_string(),
+                edges: vec![0.0],
+            },
+            column("HomePlanet"),
+            column("CryoSleep"),
+            column("CabinDeck"),
+            column("CabinSide"),
+            column("CabinDeckSide"),
+            column("Destination"),
+            column("VIP"),
+        ])
+    }
+
+    /// Runs every transform for `item`, returning the features that go into the model input in order
+    fn run(&self, item: &TitanicItem) -> Vec<(&str, FeatureValue)> {
+        let mut values: HashMap<&str, FeatureValue> = base_columns(item);
+        values.reserve(self.transforms.len());
+        let mut features = Vec::with_capacity(self.transforms.len());
+
+        for transform in self.transforms.iter() {
+            let value = transform.apply(&values);
+            values.insert(transform.name(), value);
+
+            if !self.hidden.iter().any(|hidden| hidden == transform.name()) {
+                features.push((transform.name(), value));
+            }
+        }
+
+        features
+    }
+}
+
+/// The layout of the model input, every feature in the order the batcher writes it. The schema and the pipeline that
+/// computes it are saved with the model so inference builds exactly the inputs the model was trained on.
+#[derive(Config, Debug, PartialEq)]
+pub struct FeatureSchema {
+    pub features: Vec<Feature>,
+    pub pipeline: FeaturePipeline,
+}
+
+impl FeatureSchema {
+    /// Works out the layout of the features `pipeline` makes, panicking on transforms that can't work
+    pub fn from_pipeline(pipeline: FeaturePipeline) -> Self {
+        let features = pipeline
+            .run(&shape_item())
+            .into_iter()
+            .map(|(name, value)| match value {
+                FeatureValue::Number(_) => Feature::new(name.to_string(), FeatureKind::Numeric, 1),
+                FeatureValue::Category { count, .. } => {
+                    Feature::new(name.to_string(), FeatureKind::OneHot, count)
+                }
+            })
+            .collect();
+
+        Self::new(features, pipeline)
+    }
+
+    /// Width of the model input
+    pub fn num_features(&self) -> usize {
+        self.features.iter().map(|feature| feature.width).sum()
+    }
+
+    /// Writes the features of `item` to the end of `inputs`, `num_features` values in schema order
+    pub fn encode(&self, item: &TitanicItem, inputs: &mut Vec<f32>) {
+        for (feature, (_, value)) in self.features.iter().zip(self.pipeline.run(item)) {
+            match (feature.kind, value) {
+                (FeatureKind::Numeric, FeatureValue::Number(value)) => inputs.push(value),
+                (FeatureKind::OneHot, FeatureValue::Category { index, count })
+                    if count == feature.width =>
+                {
+                    inputs.extend((0..count).map(|column| (column == index) as u8 as f32));
+                }
+                (kind, value) => panic!(
+                    "{} is {value:?}, which doesn't fit the schema's {kind:?} of width {}",
+                    feature.name, feature.width
+                ),
+            }
+        }
+    }
+}
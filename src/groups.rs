@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+
+use crate::cabins::known_cabin;
+use crate::dataset::{CabinDeck, CabinSide, TitanicItemRaw};
+
+#[derive(Debug, Clone, Default)]
+struct GroupTotals {
+    size: usize,
+    spending: f32,
+    cryo_sleep: usize,
+}
+
+/// Aggregates over the passengers of each group and each cabin, built from rows that were already imputed
+#[derive(Debug, Clone, Default)]
+pub struct GroupStats {
+    groups: HashMap<u32, GroupTotals>,
+    /// Passengers in each fully known `deck/num/side` cabin
+    cabins: HashMap<(CabinDeck, u32, CabinSide), usize>,
+}
+
+/// Total spending of a passenger, missing values count as nothing spent
+fn spending(item: &TitanicItemRaw) -> f32 {
+    [
+        item.room_service,
+        item.food_court,
+        item.shopping_mall,
+        item.spa,
+        item.vr_deck,
+    ]
+    .iter()
+    .flatten()
+    .sum()
+}
+
+impl GroupStats {
+    pub fn new(rows: &[TitanicItemRaw]) -> Self {
+        let mut stats = Self::default();
+
+        for item in rows.iter() {
+            if let Some(group) = item.group_number() {
+                let totals = stats.groups.entry(group).or_default();
+                totals.size += 1;
+                totals.spending += spending(item);
+                totals.cryo_sleep += (item.cryo_sleep.as_deref() == Some("True")) as usize;
+            }
+
+            if let Some(cabin) = known_cabin(item) {
+                *stats.cabins.entry(cabin).or_default() += 1;
+            }
+        }
+
+        stats
+    }
+
+    fn totals(&self, group: u32) -> GroupTotals {
+        self.groups.get(&group).cloned().unwrap_or(GroupTotals {
+            size: 1,
+            ..Default::default()
+        })
+    }
+
+    /// Passengers in the group, including the passenger themself
+    pub fn group_size(&self, group: u32) -> usize {
+        self.totals(group).size
+    }
+
+    /// Money spent by the whole group
+    pub fn group_spending(&self, group: u32) -> f32 {
+        self.totals(group).spending
+    }
+
+    /// Share of the group that was in cryo sleep
+    pub fn cryo_fraction(&self, group: u32) -> f32 {
+        let totals = self.totals(group);
+        totals.cryo_sleep as f32 / totals.size as f32
+    }
+
+    /// Passengers in the same cabin as `item`, including the passenger themself. Cabins without a known number
+    /// count as not shared.
+    pub fn cabin_size(&self, item: &TitanicItemRaw) -> usize {
+        known_cabin(item)
+            .and_then(|cabin| self.cabins.get(&cabin))
+            .copied()
+            .unwrap_or(1)
+    }
+}
@@ -0,0 +1,870 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use burn::config::Config;
+use rand::distributions::{Distribution, Uniform};
+use rand::seq::SliceRandom;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+use crate::dataset::TitanicItemRaw;
+
+/// How many values each imputation rule filled in, keyed by column and rule
+#[derive(Debug, Clone, Default)]
+pub struct ImputationReport {
+    counts: BTreeMap<(&'static str, &'static str), usize>,
+}
+
+impl ImputationReport {
+    pub fn record(&mut self, column: Column, rule: &'static str) {
+        *self.counts.entry((column.name(), rule)).or_default() += 1;
+    }
+}
+
+impl fmt::Display for ImputationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.counts.is_empty() {
+            return writeln!(f, "Nothing needed to be imputed");
+        }
+
+        for ((column, rule), count) in self.counts.iter() {
+            writeln!(f, "{column:<14}{rule:<16}{count:>6}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A value of a raw column, either a number or a label like `Europa` or `B/0/P`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum Value {
+    Number(f32),
+    Label(String),
+}
+
+impl Value {
+    /// A string that is equal for equal values, used to count how often a value occurs
+    fn key(&self) -> String {
+        match self {
+            Value::Number(number) => number.to_string(),
+            Value::Label(label) => label.clone(),
+        }
+    }
+}
+
+/// Columns of the raw data that can be missing
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Column {
+    HomePlanet,
+    CryoSleep,
+    Cabin,
+    Destination,
+    Age,
+    Vip,
+    RoomService,
+    FoodCourt,
+    ShoppingMall,
+    Spa,
+    VrDeck,
+}
+
+impl Column {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Column::HomePlanet => "HomePlanet",
+            Column::CryoSleep => "CryoSleep",
+            Column::Cabin => "Cabin",
+            Column::Destination => "Destination",
+            Column::Age => "Age",
+            Column::Vip => "VIP",
+            Column::RoomService => "RoomService",
+            Column::FoodCourt => "FoodCourt",
+            Column::ShoppingMall => "ShoppingMall",
+            Column::Spa => "Spa",
+            Column::VrDeck => "VRDeck",
+        }
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        matches!(
+            self,
+            Column::Age
+                | Column::RoomService
+                | Column::FoodCourt
+                | Column::ShoppingMall
+                | Column::Spa
+                | Column::VrDeck
+        )
+    }
+
+    pub fn get(&self, item: &TitanicItemRaw) -> Option<Value> {
+        let label = |value: &Option<String>| value.clone().map(Value::Label);
+        let non_empty = |value: &String| Some(value.clone()).filter(|value| !value.is_empty());
+
+        match self {
+            Column::HomePlanet => non_empty(&item.home_planet).map(Value::Label),
+            Column::CryoSleep => label(&item.cryo_sleep),
+            Column::Cabin => label(&item.cabin),
+            Column::Destination => non_empty(&item.destination).map(Value::Label),
+            Column::Age => item.age.map(Value::Number),
+            Column::Vip => label(&item.vip),
+            Column::RoomService => item.room_service.map(Value::Number),
+            Column::FoodCourt => item.food_court.map(Value::Number),
+            Column::ShoppingMall => item.shopping_mall.map(Value::Number),
+            Column::Spa => item.spa.map(Value::Number),
+            Column::VrDeck => item.vr_deck.map(Value::Number),
+        }
+    }
+
+    pub fn set(&self, item: &mut TitanicItemRaw, value: Value) {
+        match (self, value) {
+            (Column::HomePlanet, Value::Label(label)) => item.home_planet = label,
+            (Column::CryoSleep, Value::Label(label)) => item.cryo_sleep = Some(label),
+            (Column::Cabin, Value::Label(label)) => item.cabin = Some(label),
+            (Column::Destination, Value::Label(label)) => item.destination = label,
+            (Column::Age, Value::Number(number)) => item.age = Some(number),
+            (Column::Vip, Value::Label(label)) => item.vip = Some(label),
+            (Column::RoomService, Value::Number(number)) => item.room_service = Some(number),
+            (Column::FoodCourt, Value::Number(number)) => item.food_court = Some(number),
+            (Column::ShoppingMall, Value::Number(number)) => item.shopping_mall = Some(number),
+            (Column::Spa, Value::Number(number)) => item.spa = Some(number),
+            (Column::VrDeck, Value::Number(number)) => item.vr_deck = Some(number),
+            (column, value) => panic!("{value:?} is the wrong kind of value for {}", column.name()),
+        }
+    }
+
+    /// Parses a value given in a config
+    pub fn parse(&self, value: &str) -> Value {
+        if self.is_numeric() {
+            Value::Number(value.parse().unwrap_or_else(|_| {
+                panic!("{value:?} is not a number, but {} is numeric", self.name())
+            }))
+        } else {
+            Value::Label(value.to_string())
+        }
+    }
+
+    /// The placeholder for values that can't be recovered, only the columns with an `Unknown` category have one
+    fn unknown(&self) -> Option<Value> {
+        match self {
+            Column::HomePlanet | Column::Destination => Some(Value::Label("Unknown".to_string())),
+            Column::Cabin => Some(Value::Label("Unknown/Unknown/Unknown".to_string())),
+            _ => None,
+        }
+    }
+
+    fn present(&self, rows: &[TitanicItemRaw]) -> Vec<Value> {
+        rows.iter().filter_map(|item| self.get(item)).collect()
+    }
+
+    fn numbers(&self, rows: &[TitanicItemRaw], strategy: &str) -> Vec<f32> {
+        assert!(
+            self.is_numeric(),
+            "{strategy} imputation needs a numeric column, {} isn't",
+            self.name()
+        );
+
+        self.present(rows)
+            .into_iter()
+            .filter_map(|value| match value {
+                Value::Number(number) => Some(number),
+                Value::Label(_) => None,
+            })
+            .collect()
+    }
+}
+
+/// The most common value, ties go to the value that sorts first so the choice doesn't depend on row order
+fn most_common<'a>(values: impl IntoIterator<Item = &'a Value>) -> Option<Value> {
+    let mut counts: BTreeMap<String, (usize, &Value)> = BTreeMap::new();
+    for value in values {
+        counts.entry(value.key()).or_insert((0, value)).0 += 1;
+    }
+
+    counts
+        .into_values()
+        .fold(
+            None,
+            |best: Option<(usize, &Value)>, (count, value)| match best {
+                Some((best_count, _)) if best_count >= count => best,
+                _ => Some((count, value)),
+            },
+        )
+        .map(|(_, value)| value.clone())
+}
+
+/// Fills in the missing values of one column
+pub trait Imputer {
+    /// Learns whatever is needed to fill in `column` from `rows`
+    fn fit(&mut self, column: Column, rows: &[TitanicItemRaw]);
+
+    /// Fills every missing value of `column` in `rows`, recording the rule used for each one
+    fn impute(
+        &self,
+        column: Column,
+        rows: &mut [TitanicItemRaw],
+        rng: &mut dyn RngCore,
+        report: &mut ImputationReport,
+    );
+}
+
+/// Fills each missing value with the value `next` returns, counting it under `rule`
+fn fill_missing(
+    column: Column,
+    rows: &mut [TitanicItemRaw],
+    report: &mut ImputationReport,
+    rule: &'static str,
+    mut next: impl FnMut(&TitanicItemRaw) -> Option<Value>,
+) {
+    for item in rows.iter_mut().filter(|item| column.get(item).is_none()) {
+        if let Some(value) = next(item) {
+            column.set(item, value);
+            report.record(column, rule);
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ConstantImputer {
+    value: String,
+}
+
+impl Imputer for ConstantImputer {
+    fn fit(&mut self, _column: Column, _rows: &[TitanicItemRaw]) {}
+
+    fn impute(
+        &self,
+        column: Column,
+        rows: &mut [TitanicItemRaw],
+        _rng: &mut dyn RngCore,
+        report: &mut ImputationReport,
+    ) {
+        let value = column.parse(&self.value);
+        fill_missing(column, rows, report, "constant", |_| Some(value.clone()));
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct MeanImputer {
+    mean: Option<f32>,
+}
+
+impl Imputer for MeanImputer {
+    fn fit(&mut self, column: Column, rows: &[TitanicItemRaw]) {
+        let numbers = column.numbers(rows, "Mean");
+        self.mean =
+            (!numbers.is_empty()).then(|| numbers.iter().sum::<f32>() / numbers.len() as f32);
+    }
+
+    fn impute(
+        &self,
+        column: Column,
+        rows: &mut [TitanicItemRaw],
+        _rng: &mut dyn RngCore,
+        report: &mut ImputationReport,
+    ) {
+        fill_missing(column, rows, report, "mean", |_| {
+            self.mean.map(Value::Number)
+        });
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct MedianImputer {
+    median: Option<f32>,
+}
+
+impl Imputer for MedianImputer {
+    fn fit(&mut self, column: Column, rows: &[TitanicItemRaw]) {
+        let mut numbers = column.numbers(rows, "Median");
+        numbers.sort_by(f32::total_cmp);
+        self.median = numbers.get(numbers.len() / 2).copied();
+    }
+
+    fn impute(
+        &self,
+        column: Column,
+        rows: &mut [TitanicItemRaw],
+        _rng: &mut dyn RngCore,
+        report: &mut ImputationReport,
+    ) {
+        fill_missing(column, rows, report, "median", |_| {
+            self.median.map(Value::Number)
+        });
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ModeImputer {
+    mode: Option<Value>,
+}
+
+impl Imputer for ModeImputer {
+    fn fit(&mut self, column: Column, rows: &[TitanicItemRaw]) {
+        self.mode = most_common(column.present(rows).iter());
+    }
+
+    fn impute(
+        &self,
+        column: Column,
+        rows: &mut [TitanicItemRaw],
+        _rng: &mut dyn RngCore,
+        report: &mut ImputationReport,
+    ) {
+        fill_missing(column, rows, report, "mode", |_| self.mode.clone());
+    }
+}
+
+/// Draws each missing value from the values seen in the fitted rows, which keeps the column's distribution
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct SampleImputer {
+    values: Vec<Value>,
+}
+
+impl Imputer for SampleImputer {
+    fn fit(&mut self, column: Column, rows: &[TitanicItemRaw]) {
+        self.values = column.present(rows);
+    }
+
+    fn impute(
+        &self,
+        column: Column,
+        rows: &mut [TitanicItemRaw],
+        rng: &mut dyn RngCore,
+        report: &mut ImputationReport,
+    ) {
+        fill_missing(column, rows, report, "sampled", |_| {
+            self.values.choose(&mut *rng).cloned()
+        });
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct UniformImputer {
+    min: f32,
+    max: f32,
+}
+
+impl Imputer for UniformImputer {
+    fn fit(&mut self, column: Column, _rows: &[TitanicItemRaw]) {
+        assert!(
+            column.is_numeric(),
+            "Uniform imputation needs a numeric column, {} isn't",
+            column.name()
+        );
+    }
+
+    fn impute(
+        &self,
+        column: Column,
+        rows: &mut [TitanicItemRaw],
+        rng: &mut dyn RngCore,
+        report: &mut ImputationReport,
+    ) {
+        let dist = Uniform::new(self.min, self.max);
+        fill_missing(column, rows, report, "uniform", |_| {
+            Some(Value::Number(dist.sample(&mut *rng)))
+        });
+    }
+}
+
+/// The value group-mates share, only the deck and side of a cabin are shared so its number is left unknown
+fn shared_value(column: Column, item: &TitanicItemRaw) -> Option<Value> {
+    match (column, column.get(item)?) {
+        (Column::Cabin, Value::Label(cabin)) => match cabin.split('/').collect::<Vec<&str>>()[..] {
+            [deck, _, side] if deck != "Unknown" && side != "Unknown" => {
+                Some(Value::Label(format!("{deck}/Unknown/{side}")))
+            }
+            _ => None,
+        },
+        (_, value) => Some(value),
+    }
+}
+
+/// Values of a column seen in each group, overall and per surname
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct GroupIndex {
+    groups: HashMap<u32, Vec<Value>>,
+    surnames: HashMap<u32, HashMap<String, Vec<Value>>>,
+}
+
+impl GroupIndex {
+    fn new(column: Column, rows: &[TitanicItemRaw]) -> Self {
+        let mut index = Self::default();
+
+        for item in rows.iter() {
+            let (Some(group), Some(value)) = (item.group_number(), shared_value(column, item))
+            else {
+                continue;
+            };
+
+            if let Some(surname) = item.surname() {
+                index
+                    .surnames
+                    .entry(group)
+                    .or_default()
+                    .entry(surname.to_string())
+                    .or_default()
+                    .push(value.clone());
+            }
+            index.groups.entry(group).or_default().push(value);
+        }
+
+        index
+    }
+
+    fn group(&self, group: u32) -> &[Value] {
+        self.groups
+            .get(&group)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    fn surname(&self, group: u32, surname: &str) -> &[Value] {
+        self.surnames
+            .get(&group)
+            .and_then(|surnames| surnames.get(surname))
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+}
+
+/// Fills a column from group-mates, preferring a mate with the same surname over any other mate. Mates are looked
+/// up in both the fitted rows and the rows being imputed. Passengers travelling alone get `Unknown` where the column
+/// has it, and the most common value otherwise.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct GroupImputer {
+    index: GroupIndex,
+    fallback: Option<Value>,
+}
+
+impl Imputer for GroupImputer {
+    fn fit(&mut self, column: Column, rows: &[TitanicItemRaw]) {
+        self.index = GroupIndex::new(column, rows);
+        self.fallback = column
+            .unknown()
+            .or_else(|| most_common(column.present(rows).iter()));
+    }
+
+    fn impute(
+        &self,
+        column: Column,
+        rows: &mut [TitanicItemRaw],
+        _rng: &mut dyn RngCore,
+        report: &mut ImputationReport,
+    ) {
+        let local = GroupIndex::new(column, rows);
+
+        for item in rows.iter_mut().filter(|item| column.get(item).is_none()) {
+            let (same_surname, same_group) = match item.group_number() {
+                Some(group) => {
+                    let surname = item.surname().unwrap_or_default();
+                    (
+                        most_common(
+                            self.index
+                                .surname(group, surname)
+                                .iter()
+                                .chain(local.surname(group, surname)),
+                        ),
+                        most_common(self.index.group(group).iter().chain(local.group(group))),
+                    )
+                }
+                None => (None, None),
+            };
+
+            let (value, rule) = match (same_surname, same_group) {
+                (Some(value), _) => (value, "group surname"),
+                (None, Some(value)) => (value, "group"),
+                (None, None) => match &self.fallback {
+                    Some(value) => (value.clone(), "group fallback"),
+                    None => continue,
+                },
+            };
+
+            column.set(item, value);
+            report.record(column, rule);
+        }
+    }
+}
+
+/// The numbers KNN measures distances on, missing entries are skipped when comparing two rows
+fn knn_features(item: &TitanicItemRaw) -> [Option<f32>; 8] {
+    let flag =
+        |value: &Option<String>| value.as_deref().map(|value| (value == "True") as u8 as f32);
+    let spend = |value: Option<f32>| value.map(f32::ln_1p);
+
+    [
+        item.age,
+        flag(&item.cryo_sleep),
+        flag(&item.vip),
+        spend(item.room_service),
+        spend(item.food_court),
+        spend(item.shopping_mall),
+        spend(item.spa),
+        spend(item.vr_deck),
+    ]
+}
+
+/// Fills a value from the `k` fitted rows closest to the passenger, averaging numbers and voting on labels
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct KnnImputer {
+    k: usize,
+    neighbours: Vec<([Option<f32>; 8], Value)>,
+    scale: [f32; 8],
+}
+
+impl KnnImputer {
+    fn distance(&self, a: &[Option<f32>; 8], b: &[Option<f32>; 8]) -> f32 {
+        let (total, shared) = a
+            .iter()
+            .zip(b.iter())
+            .zip(self.scale.iter())
+            .filter_map(|((a, b), scale)| Some(((a.as_ref()? - b.as_ref()?) / scale).powi(2)))
+            .fold((0.0, 0), |(total, shared), distance| {
+                (total + distance, shared + 1)
+            });
+
+        match shared {
+            0 => f32::INFINITY,
+            shared => total / shared as f32,
+        }
+    }
+}
+
+impl Imputer for KnnImputer {
+    fn fit(&mut self, column: Column, rows: &[TitanicItemRaw]) {
+        self.neighbours = rows
+            .iter()
+            .filter_map(|item| Some((knn_features(item), column.get(item)?)))
+            .collect();
+
+        for (feature, scale) in self.scale.iter_mut().enumerate() {
+            let values: Vec<f32> = self
+                .neighbours
+                .iter()
+                .filter_map(|(features, _)| features[feature])
+                .collect();
+            let count = values.len().max(1) as f32;
+            let mean = values.iter().sum::<f32>() / count;
+            let variance = values
+                .iter()
+                .map(|value| (value - mean).powi(2))
+                .sum::<f32>()
+                / count;
+
+            *scale = if variance > 0.0 { variance.sqrt() } else { 1.0 };
+        }
+    }
+
+    fn impute(
+        &self,
+        column: Column,
+        rows: &mut [TitanicItemRaw],
+        _rng: &mut dyn RngCore,
+        report: &mut ImputationReport,
+    ) {
+        fill_missing(column, rows, report, "knn", |item| {
+            let features = knn_features(item);
+            let mut nearest: Vec<(f32, &Value)> = self
+                .neighbours
+                .iter()
+                .map(|(other, value)| (self.distance(&features, other), value))
+                .filter(|(distance, _)| distance.is_finite())
+                .collect();
+            nearest.sort_by(|a, b| a.0.total_cmp(&b.0));
+            nearest.truncate(self.k);
+
+            match nearest.first()?.1 {
+                Value::Number(_) => {
+                    let numbers: Vec<f32> = nearest
+                        .iter()
+                        .filter_map(|(_, value)| match value {
+                            Value::Number(number) => Some(*number),
+                            Value::Label(_) => None,
+                        })
+                        .collect();
+                    Some(Value::Number(
+                        numbers.iter().sum::<f32>() / numbers.len() as f32,
+                    ))
+                }
+                Value::Label(_) => most_common(nearest.iter().map(|(_, value)| *value)),
+            }
+        });
+    }
+}
+
+/// How the missing values of a column get filled in
+#[derive(Config, Debug)]
+pub enum ImputeStrategy {
+    /// The same value every time, parsed as a number for numeric columns
+    Constant { value: String },
+    /// The mean of the column, numeric columns only
+    Mean,
+    /// The median of the column, numeric columns only
+    Median,
+    /// The most common value of the column
+    Mode,
+    /// A random value drawn from the values seen in the column
+    Sample,
+    /// A random number between `min` and `max`, numeric columns only
+    Uniform { min: f32, max: f32 },
+    /// The value shared by the passenger's group-mates
+    Group,
+    /// The value of the `k` most similar passengers
+    Knn { k: usize },
+}
+
+/// The imputer of one column, an enum rather than a trait object so the fitted state can be saved with the model
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum ColumnImputer {
+    Constant(ConstantImputer),
+    Mean(MeanImputer),
+    Median(MedianImputer),
+    Mode(ModeImputer),
+    Sample(SampleImputer),
+    Uniform(UniformImputer),
+    Group(GroupImputer),
+    Knn(KnnImputer),
+}
+
+impl ColumnImputer {
+    fn inner(&self) -> &dyn Imputer {
+        match self {
+            ColumnImputer::Constant(imputer) => imputer,
+            ColumnImputer::Mean(imputer) => imputer,
+            ColumnImputer::Median(imputer) => imputer,
+            ColumnImputer::Mode(imputer) => imputer,
+            ColumnImputer::Sample(imputer) => imputer,
+            ColumnImputer::Uniform(imputer) => imputer,
+            ColumnImputer::Group(imputer) => imputer,
+            ColumnImputer::Knn(imputer) => imputer,
+        }
+    }
+
+    fn inner_mut(&mut self) -> &mut dyn Imputer {
+        match self {
+            ColumnImputer::Constant(imputer) => imputer,
+            ColumnImputer::Mean(imputer) => imputer,
+            ColumnImputer::Median(imputer) => imputer,
+            ColumnImputer::Mode(imputer) => imputer,
+            ColumnImputer::Sample(imputer) => imputer,
+            ColumnImputer::Uniform(imputer) => imputer,
+            ColumnImputer::Group(imputer) => imputer,
+            ColumnImputer::Knn(imputer) => imputer,
+        }
+    }
+}
+
+impl Imputer for ColumnImputer {
+    fn fit(&mut self, column: Column, rows: &[TitanicItemRaw]) {
+        self.inner_mut().fit(column, rows);
+    }
+
+    fn impute(
+        &self,
+        column: Column,
+        rows: &mut [TitanicItemRaw],
+        rng: &mut dyn RngCore,
+        report: &mut ImputationReport,
+    ) {
+        self.inner().impute(column, rows, rng, report);
+    }
+}
+
+impl ImputeStrategy {
+    pub fn init(&self) -> ColumnImputer {
+        match self {
+            ImputeStrategy::Constant { value } => ColumnImputer::Constant(ConstantImputer {
+                value: value.clone(),
+            }),
+            ImputeStrategy::Mean => ColumnImputer::Mean(MeanImputer::default()),
+            ImputeStrategy::Median => ColumnImputer::Median(MedianImputer::default()),
+            ImputeStrategy::Mode => ColumnImputer::Mode(ModeImputer::default()),
+            ImputeStrategy::Sample => ColumnImputer::Sample(SampleImputer::default()),
+            ImputeStrategy::Uniform { min, max } => ColumnImputer::Uniform(UniformImputer {
+                min: *min,
+                max: *max,
+            }),
+            ImputeStrategy::Group => ColumnImputer::Group(GroupImputer::default()),
+            ImputeStrategy::Knn { k } => ColumnImputer::Knn(KnnImputer {
+                k: *k,
+                neighbours: Vec::new(),
+                scale: [1.0; 8],
+            }),
+        }
+    }
+}
+
+/// The imputation strategy of every column
+#[derive(Config, Debug)]
+pub struct ImputationConfig {
+    #[config(default = "ImputeStrategy::Constant { value: \"0\".to_string() }")]
+    pub room_service: ImputeStrategy,
+    #[config(default = "ImputeStrategy::Constant { value: \"0\".to_string() }")]
+    pub food_court: ImputeStrategy,
+    #[config(default = "ImputeStrategy::Constant { value: \"0\".to_string() }")]
+    pub shopping_mall: ImputeStrategy,
+    #[config(default = "ImputeStrategy::Constant { value: \"0\".to_string() }")]
+    pub spa: ImputeStrategy,
+    #[config(default = "ImputeStrategy::Constant { value: \"0\".to_string() }")]
+    pub vr_deck: ImputeStrategy,
+    #[config(default = "ImputeStrategy::Sample")]
+    pub cryo_sleep: ImputeStrategy,
+    #[config(default = "ImputeStrategy::Group")]
+    pub cabin: ImputeStrategy,
+    #[config(default = "ImputeStrategy::Group")]
+    pub home_planet: ImputeStrategy,
+    #[config(default = "ImputeStrategy::Group")]
+    pub destination: ImputeStrategy,
+    #[config(default = "ImputeStrategy::Uniform { min: 15.0, max: 47.0 }")]
+    pub age: ImputeStrategy,
+    // VIP doesn't seem to be shared in families
+    #[config(default = "ImputeStrategy::Constant { value: \"False\".to_string() }")]
+    pub vip: ImputeStrategy,
+}
+
+impl ImputationConfig {
+    /// Every column with its strategy, in the order they are imputed. Spending comes first so KNN has it to work
+    /// with for the other columns.
+    pub fn strategies(&self) -> [(Column, &ImputeStrategy); 11] {
+        [
+            (Column::RoomService, &self.room_service),
+            (Column::FoodCourt, &self.food_court),
+            (Column::ShoppingMall, &self.shopping_mall),
+            (Column::Spa, &self.spa),
+            (Column::VrDeck, &self.vr_deck),
+            (Column::CryoSleep, &self.cryo_sleep),
+            (Column::Cabin, &self.cabin),
+            (Column::HomePlanet, &self.home_planet),
+            (Column::Destination, &self.destination),
+            (Column::Age, &self.age),
+            (Column::Vip, &self.vip),
+        ]
+    }
+
+    /// Fits every column's imputer on `rows`, which should only ever be the training split so nothing about the
+    /// validation or submission rows leaks into the fitted state
+    pub fn fit(&self, rows: &[TitanicItemRaw]) -> FittedImputation {
+        let imputers = self
+            .strategies()
+            .into_iter()
+            .map(|(column, strategy)| {
+                let mut imputer = strategy.init();
+                imputer.fit(column, rows);
+                (column, imputer)
+            })
+            .collect();
+
+        FittedImputation::new(imputers)
+    }
+}
+
+/// Imputers fitted on the training split, saved next to the model so inference fills values the same way
+#[derive(Config, Debug)]
+pub struct FittedImputation {
+    /// Every column with its fitted imputer, in the order they are applied
+    pub imputers: Vec<(Column, ColumnImputer)>,
+}
+
+impl FittedImputation {
+    /// Fills in the missing values of `rows` with the fitted imputers
+    pub fn transform(
+        &self,
+        rows: &mut [TitanicItemRaw],
+        rng: &mut dyn RngCore,
+    ) -> ImputationReport {
+        let mut report = ImputationReport::default();
+
+        for (column, imputer) in self.imputers.iter() {
+            imputer.impute(*column, rows, rng, &mut report);
+        }
+
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    fn passenger(id: &str, name: &str, home_planet: &str, cabin: Option<&str>) -> TitanicItemRaw {
+        TitanicItemRaw {
+            passenger_id: id.to_string(),
+            home_planet: home_planet.to_string(),
+            cryo_sleep: Some("False".to_string()),
+            cabin: cabin.map(str::to_string),
+            destination: "TRAPPIST-1e".to_string(),
+            age: Some(30.0),
+            vip: Some("False".to_string()),
+            room_service: Some(0.0),
+            food_court: Some(0.0),
+            shopping_mall: Some(0.0),
+            spa: Some(0.0),
+            vr_deck: Some(0.0),
+            name: Some(name.to_string()),
+            transported: None,
+            row: 0,
+        }
+    }
+
+    fn impute(column: Column, rows: &mut [TitanicItemRaw]) -> ImputationReport {
+        let mut imputer = GroupImputer::default();
+        imputer.fit(column, rows);
+
+        let mut report = ImputationReport::default();
+        imputer.impute(column, rows, &mut StdRng::seed_from_u64(42), &mut report);
+        report
+    }
+
+    fn count(report: &ImputationReport, column: Column, rule: &'static str) -> usize {
+        report
+            .counts
+            .get(&(column.name(), rule))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    fn rows() -> Vec<TitanicItemRaw> {
+        vec![
+            passenger("0001_01", "Ann Smith", "Europa", Some("B/1/P")),
+            passenger("0001_02", "Cid Smith", "Europa", Some("B/1/P")),
+            passenger("0001_03", "Dee Jones", "Earth", Some("F/7/S")),
+            passenger("0001_04", "Eve Jones", "", None),
+            passenger("0002_01", "Fay Lee", "Mars", Some("C/3/P")),
+            passenger("0002_02", "Gus Kim", "", None),
+            passenger("0003_01", "Hal Solo", "", None),
+        ]
+    }
+
+    #[test]
+    fn group_imputer_prefers_mates_with_the_same_surname() {
+        let mut rows = rows();
+        let report = impute(Column::HomePlanet, &mut rows);
+
+        // The group mostly came from Europa, but the only other Jones came from Earth
+        assert_eq!(rows[3].home_planet, "Earth");
+        assert_eq!(rows[5].home_planet, "Mars");
+        assert_eq!(rows[6].home_planet, "Unknown");
+
+        assert_eq!(count(&report, Column::HomePlanet, "group surname"), 1);
+        assert_eq!(count(&report, Column::HomePlanet, "group"), 1);
+        assert_eq!(count(&report, Column::HomePlanet, "group fallback"), 1);
+    }
+
+    #[test]
+    fn group_imputer_shares_only_deck_and_side_of_cabins() {
+        let mut rows = rows();
+        let report = impute(Column::Cabin, &mut rows);
+
+        assert_eq!(rows[3].cabin.as_deref(), Some("F/Unknown/S"));
+        assert_eq!(rows[5].cabin.as_deref(), Some("C/Unknown/P"));
+        assert_eq!(rows[6].cabin.as_deref(), Some("Unknown/Unknown/Unknown"));
+
+        assert_eq!(count(&report, Column::Cabin, "group surname"), 1);
+        assert_eq!(count(&report, Column::Cabin, "group"), 1);
+        assert_eq!(count(&report, Column::Cabin, "group fallback"), 1);
+    }
+}
@@ -1,45 +1,241 @@
-use crate::{
-    data::TitanicBatcher,
-    dataset::{TitanicDataset, TitanicItem},
-    model::Model,
-    training::TrainingConfig,
-};
-use burn::{
-    data::{dataloader::batcher::Batcher, dataset::Dataset},
-    prelude::*,
-    record::{CompactRecorder, NoStdInferenceRecorder, NoStdTrainingRecorder, Recorder},
-};
-
-pub fn infer<B: Backend<IntElem = i64>>(
-    artifact_dir: &str,
-    device: B::Device,
-    dataset: TitanicDataset,
-) {
-    let config = TrainingConfig::load(format!("{artifact_dir}/config.json"))
-        .expect("Config should exist for the model");
-    let record = CompactRecorder::new()
-        .load(format!("{artifact_dir}/model").into(), &device)
-        .expect("Trained model should exist");
-
-    let model: Model<B> = config.model.init(&device).load_record(record);
-
-    let batcher = TitanicBatcher::new(device);
-    println!("PassengerId,Transported");
-    for item in dataset.iter() {
-        let batch = batcher.batch(vec![item.clone()]);
-        let output = model.forward(batch.inputs);
-        let predicted = output.argmax(1).flatten::<1>(0, 1).into_scalar();
-
-        let output = format!(
-            "{:04}_{:02},{}",
-            &item.group_number,
-            &item.passenger_number,
-            match predicted {
-                1 => "True",
-                _ => "False",
-            }
-        );
-
-        println!("{}", output);
-    }
-}
+use crate::{
+    cli::{InferArgs, RunArgs},
+    data::TitanicBatcher,
+    dataset::{TitanicDataset, TitanicItem},
+    features::FeatureSchema,
+    impute::FittedImputation,
+    model::{transported_probability, Model},
+    predictions::{fold_of, write_predictions, PredictionRow},
+    scaling::FittedScaler,
+    submission::{check_submission, write_submission, SubmissionRow},
+    threshold::DecisionThreshold,
+    training::TrainingConfig,
+};
+use burn::{
+    data::{
+        dataloader::{batcher::Batcher, DataLoaderBuilder},
+        dataset::Dataset,
+    },
+    prelude::*,
+    record::{CompactRecorder, Recorder},
+    tensor::ElementConversion,
+};
+
+/// Everything training saved to the artifact directory
+pub(crate) struct Artifacts<B: Backend> {
+    pub config: TrainingConfig,
+    pub imputation: FittedImputation,
+    pub schema: FeatureSchema,
+    pub scaler: FittedScaler,
+    pub threshold: DecisionThreshold,
+    pub model: Model<B>,
+}
+
+pub(crate) fn load_model<B: Backend>(artifact_dir: &str, device: &B::Device) -> Artifacts<B> {
+    let config = TrainingConfig::load(format!("{artifact_dir}/config.json"))
+        .expect("Config should exist for the model");
+    let imputation = FittedImputation::load(format!("{artifact_dir}/imputation.json"))
+        .expect("Fitted imputation should exist for the model");
+    let schema = FeatureSchema::load(format!("{artifact_dir}/schema.json"))
+        .expect("Feature schema should exist for the model");
+    let scaler = FittedScaler::load(format!("{artifact_dir}/scaler.json"))
+        .expect("Fitted scaler should exist for the model");
+    let threshold = DecisionThreshold::load(format!("{artifact_dir}/threshold.json"))
+        .expect("Decision threshold should exist for the model");
+    let record = CompactRecorder::new()
+        .load(format!("{artifact_dir}/model").into(), device)
+        .expect("Trained model should exist");
+
+    // Loaded models only ever predict, and dropout would stay active if they run on an autodiff backend
+    let model = config
+        .model
+        .without_dropout()
+        .init(schema.num_features(), device)
+        .load_record(record);
+    Artifacts {
+        config,
+        imputation,
+        schema,
+        scaler,
+        threshold,
+        model,
+    }
+}
+
+pub fn infer<B: Backend>(args: &InferArgs, device: B::Device) {
+    let artifacts = load_model::<B>(&args.run.artifact_dir, &device);
+    let dataset = TitanicDataset::submission(
+        &args.run.data.paths(),
+        args.run.data.bad_rows,
+        &artifacts.config.split,
+        &artifacts.imputation,
+        &artifacts.config.cabins,
+        artifacts.config.seed,
+    )
+    .unwrap_or_else(|err| panic!("Failed to load the submission data: {err}"));
+
+    let batcher = TitanicBatcher::new(device, artifacts.schema, artifacts.scaler);
+    // A single worker keeps the batches, and so the submission, in file order
+    let dataloader = DataLoaderBuilder::new(batcher)
+        .batch_size(artifacts.config.batch_size)
+        .build(dataset);
+
+    let model_id = args
+        .model_id
+        .clone()
+        .unwrap_or_else(|| args.run.artifact_dir.clone());
+    let fold = fold_of(&args.run.artifact_dir);
+
+    let mut rows = Vec::new();
+    let mut predictions = Vec::new();
+    for batch in dataloader.iter() {
+        let logits = artifacts.model.forward(batch.inputs, batch.categories);
+        let probabilities: Vec<f64> = transported_probability(logits.clone())
+            .into_data()
+            .convert::<f64>()
+            .value;
+        let logits: Vec<f64> = logits.into_data().convert::<f64>().value;
+
+        for ((passenger_id, probability), logits) in batch
+            .passenger_ids
+            .into_iter()
+            .zip(probabilities)
+            .zip(logits.chunks(2))
+        {
+            let decision = artifacts.threshold.decide(probability);
+            let transported = if args.probabilities {
+                format!("{probability:.6}")
+            } else if decision {
+                "True".to_string()
+            } else {
+                "False".to_string()
+            };
+
+            predictions.push(PredictionRow {
+                passenger_id: passenger_id.clone(),
+                probability,
+                logit_not_transported: logits[0],
+                logit_transported: logits[1],
+                transported: decision,
+                threshold: artifacts.threshold.threshold,
+                model: model_id.clone(),
+                fold,
+            });
+            rows.push(SubmissionRow {
+                passenger_id,
+                transported,
+            });
+        }
+    }
+
+    check_submission(&rows, &args.sample_submission)
+        .unwrap_or_else(|err| panic!("The submission doesn't match the sample: {err}"));
+
+    if let Some(path) = &args.predictions {
+        write_predictions(path, args.predictions_format, &predictions)
+            .unwrap_or_else(|err| panic!("Failed to write the predictions: {err}"));
+        println!(
+            "Wrote {} predictions to {}",
+            predictions.len(),
+            path.display()
+        );
+    }
+
+    write_submission(&args.output, &rows)
+        .unwrap_or_else(|err| panic!("Failed to write the submission: {err}"));
+    println!(
+        "Wrote {} predictions to {}",
+        rows.len(),
+        args.output.display()
+    );
+}
+
+/// P(Transported) of every item, run as a single batch
+pub fn probabilities<B: Backend>(
+    model: &Model<B>,
+    batcher: &TitanicBatcher<B>,
+    items: Vec<TitanicItem>,
+) -> Vec<f64> {
+    let batch = batcher.batch(items);
+    model
+        .probabilities(batch.inputs, batch.categories)
+        .into_data()
+        .convert::<f64>()
+        .value
+}
+
+/// How well a model does on a set of passengers
+#[derive(Debug, Clone, Copy)]
+pub struct Score {
+    /// Accuracy of the argmax decision, which is a threshold of 0.5
+    pub accuracy: f64,
+    /// Accuracy of deciding with the tuned threshold
+    pub threshold_accuracy: f64,
+    pub loss: f64,
+}
+
+/// Accuracy with and without `threshold`, and loss of `model` on `items`, run as a single batch
+pub fn score<B: Backend>(
+    model: &Model<B>,
+    batcher: &TitanicBatcher<B>,
+    items: Vec<TitanicItem>,
+    threshold: &DecisionThreshold,
+) -> Score {
+    let num_items = items.len() as f64;
+
+    let output = model.forward_step(batcher.batch(items));
+    let correct = output
+        .output
+        .clone()
+        .argmax(1)
+        .squeeze::<1>(1)
+        .equal(output.targets.clone())
+        .int()
+        .sum()
+        .into_scalar()
+        .elem::<f64>();
+
+    let probabilities: Vec<f64> = transported_probability(output.output)
+        .into_data()
+        .convert::<f64>()
+        .value;
+    let targets: Vec<i64> = output.targets.into_data().convert::<i64>().value;
+    let threshold_correct = probabilities
+        .iter()
+        .zip(targets.iter())
+        .filter(|(probability, target)| threshold.decide(**probability) == (**target == 1))
+        .count() as f64;
+
+    Score {
+        accuracy: correct / num_items,
+        threshold_accuracy: threshold_correct / num_items,
+        loss: output.loss.into_scalar().elem::<f64>(),
+    }
+}
+
+pub fn evaluate<B: Backend>(args: &RunArgs, device: B::Device) {
+    let artifacts = load_model::<B>(&args.artifact_dir, &device);
+    let dataset = TitanicDataset::valid(
+        &args.data.paths(),
+        args.data.bad_rows,
+        &artifacts.config.split,
+        &artifacts.imputation,
+        &artifacts.config.cabins,
+        artifacts.config.seed,
+    )
+    .unwrap_or_else(|err| panic!("Failed to load the validation data: {err}"));
+
+    let items: Vec<TitanicItem> = dataset.iter().collect();
+    let num_items = items.len();
+    let batcher = TitanicBatcher::new(device, artifacts.schema, artifacts.scaler);
+    let score = score(&artifacts.model, &batcher, items, &artifacts.threshold);
+
+    println!("Evaluated {num_items} entries");
+    println!("Accuracy: {:.2}%", 100.0 * score.accuracy);
+    println!(
+        "Accuracy at threshold {:.2}: {:.2}% (in-sample, the threshold was tuned on this split)",
+        artifacts.threshold.threshold,
+        100.0 * score.threshold_accuracy
+    );
+    println!("Loss: {:.4}", score.loss);
+}
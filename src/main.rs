@@ -1,32 +1,125 @@
-use burn::backend::{Autodiff, LibTorch};
+#[cfg(feature = "tch")]
+use burn::backend::LibTorch;
+#[cfg(feature = "ndarray")]
+use burn::backend::NdArray;
+#[cfg(feature = "wgpu")]
+use burn::backend::Wgpu;
+use burn::{backend::Autodiff, data::dataset::Dataset, tensor::backend::Backend};
+use clap::Parser;
 
+mod attribution;
+mod backend;
+mod cabins;
+mod cli;
 mod data;
 mod dataset;
+mod error;
+mod features;
+mod groups;
+mod impute;
 mod inference;
 mod model;
+mod names;
+mod predictions;
+mod scaling;
+mod split;
+mod submission;
+mod threshold;
 mod training;
+mod validation;
 
-use inference::infer;
-use training::run;
+use attribution::explain;
+use backend::BackendKind;
+use cli::{Cli, Command, InspectArgs};
+use dataset::TitanicDataset;
+use inference::{evaluate, infer};
+use training::{cross_validate, run, TrainingConfig};
+use validation::ValidationReport;
 
 fn main() {
-    // type WgpuBackend = Wgpu<AutoGraphicsApi, f32, i32>;
-    type TorchBackend = LibTorch<f32>;
-
-    // type AutodiffWgpu = Autodiff<WgpuBackend>;
-    type AutodiffTorch = Autodiff<TorchBackend>;
-
-    // let device = burn::backend::wgpu::WgpuDevice::default();
-    // let cuda_device = burn::backend::wgpu::WgpuDevice::IntegratedGpu(0);
-    // println!("Cuda : {:?}", cuda_device);
-    // train::<AutodiffWgpu>("/tmp/burn", TrainingConfig::new(ModelConfig::new(10, 512), AdamConfig::new()), cuda_device)
-    let device = burn::backend::libtorch::LibTorchDevice::Cpu;
-    let cuda_device = burn::backend::libtorch::LibTorchDevice::Cuda(0);
-    // println!("{:?}", cuda_device);
-    run::<AutodiffTorch>(device);
-    // infer::<AutodiffTorch>(
-    //     "/tmp/titanic",
-    //     device,
-    //     dataset::TitanicDataset::submission(),
-    // )
+    let cli = Cli::parse();
+
+    let args = match &cli.command {
+        Command::InspectData(args) => return inspect_data(args),
+        Command::Train(args) => args.run.clone(),
+        Command::Evaluate(args) => args.clone(),
+        Command::Infer(args) => args.run.clone(),
+        Command::CrossValidate(args) => args.run.clone(),
+        Command::Explain(args) => args.run.clone(),
+    };
+
+    match args.backend {
+        #[cfg(feature = "ndarray")]
+        BackendKind::NdArray => {
+            execute::<NdArray<f32>>(cli.command, backend::ndarray_device(&args.device))
+        }
+        #[cfg(feature = "tch")]
+        BackendKind::Tch => {
+            execute::<LibTorch<f32>>(cli.command, backend::libtorch_device(&args.device))
+        }
+        #[cfg(feature = "wgpu")]
+        BackendKind::Wgpu => execute::<Wgpu>(cli.command, backend::wgpu_device(&args.device)),
+    }
+}
+
+fn execute<B: Backend>(command: Command, device: B::Device) {
+    match command {
+        Command::Train(args) => run::<Autodiff<B>>(&args, device),
+        Command::CrossValidate(args) => cross_validate::<Autodiff<B>>(&args, device),
+        Command::Infer(args) => infer::<B>(&args, device),
+        Command::Evaluate(args) => evaluate::<B>(&args, device),
+        Command::Explain(args) => explain::<Autodiff<B>>(&args, device),
+        Command::InspectData(args) => inspect_data(&args),
+    }
+}
+
+fn inspect_data(args: &InspectArgs) {
+    let paths = &args.data.paths();
+    let policy = args.data.bad_rows;
+    for path in [&paths.train, &paths.test] {
+        match ValidationReport::from_path(path) {
+            Ok(report) => println!("{report}"),
+            Err(err) => println!("Failed to validate {}: {err}", path.display()),
+        }
+    }
+
+    let config = TrainingConfig::load_or_default(args.config.as_deref());
+    let loaded = TitanicDataset::train_valid(
+        paths,
+        policy,
+        &config.split,
+        &config.imputation,
+        &config.cabins,
+        config.seed,
+    )
+    .and_then(|(train, test, imputation)| {
+        let submission = TitanicDataset::submission(
+            paths,
+            policy,
+            &config.split,
+            &imputation,
+            &config.cabins,
+            config.seed,
+        )?;
+        Ok((train, test, submission))
+    });
+
+    // The reports above already list every problem, so a row the policy can't handle ends the inspection here
+    let (train, test, submission) = match loaded {
+        Ok(datasets) => datasets,
+        Err(err) => {
+            println!("Failed to load the data with the {policy:?} bad-row policy: {err}");
+            return;
+        }
+    };
+
+    println!("Train data is {} entries", train.len());
+    println!("Test data is {} entries", test.len());
+    println!("Submission data is {} entries", submission.len());
+
+    println!("Imputed training values:\n{}", train.imputation_report());
+    println!(
+        "Imputed submission values:\n{}",
+        submission.imputation_report()
+    );
 }
@@ -3,93 +3,293 @@ use burn::{
     module::Module,
     nn::{
         loss::{BinaryCrossEntropyLossConfig, CrossEntropyLossConfig},
-        Dropout, DropoutConfig, Linear, LinearConfig, Relu,
+        BatchNorm, BatchNormConfig, Dropout, DropoutConfig, Embedding, EmbeddingConfig, LayerNorm,
+        LayerNormConfig, Linear, LinearConfig,
     },
     tensor::{
+        activation,
         backend::{AutodiffBackend, Backend},
-        Tensor,
+        Int, Tensor,
     },
     train::{ClassificationOutput, TrainOutput, TrainStep, ValidStep},
 };
 
+use serde::{Deserialize, Serialize};
+
 use crate::data::TitanicBatch;
+use crate::features::CATEGORY_COUNTS;
+
+/// The non-linearity after a hidden layer
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Activation {
+    Relu,
+    Gelu,
+    /// ReLU that lets through a hundredth of negative values
+    LeakyRelu,
+    Silu,
+}
+
+burn::constant!(Activation);
+
+impl Activation {
+    fn forward<B: Backend>(&self, x: Tensor<B, 2>) -> Tensor<B, 2> {
+        match self {
+            Activation::Relu => activation::relu(x),
+            Activation::Gelu => activation::gelu(x),
+            Activation::LeakyRelu => activation::relu(x.clone()) - activation::relu(x.neg()) * 0.01,
+            Activation::Silu => activation::silu(x),
+        }
+    }
+}
+
+/// Normalization of a hidden layer's outputs, before the activation
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Normalization {
+    None,
+    Batch,
+    Layer,
+}
+
+/// How the model turns its last hidden layer into a prediction, and the loss it is trained with
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum Head {
+    /// Two logits trained with cross-entropy
+    Softmax,
+    /// One logit trained with binary cross-entropy. A `label_smoothing` of 0 leaves the labels as they are, and the
+    /// loss of transported passengers is multiplied by `positive_weight`.
+    Sigmoid {
+        label_smoothing: f32,
+        positive_weight: f32,
+    },
+}
+
+burn::constant!(Head);
+
+impl Head {
+    fn num_logits(&self) -> usize {
+        match self {
+            Head::Softmax => 2,
+            Head::Sigmoid { .. } => 1,
+        }
+    }
+}
+
+#[derive(Module, Debug)]
+pub struct HiddenLayer<B: Backend> {
+    linear: Linear<B>,
+    batch_norm: Option<BatchNorm<B, 0>>,
+    layer_norm: Option<LayerNorm<B>>,
+    activation: Activation,
+    dropout: Dropout,
+    /// Whether the layer's input is added to its output, only ever set when both are as wide
+    residual: bool,
+}
+
+impl<B: Backend> HiddenLayer<B> {
+    pub fn forward(&self, input: Tensor<B, 2>) -> Tensor<B, 2> {
+        let mut x = self.linear.forward(input.clone());
+        if let Some(norm) = &self.batch_norm {
+            x = norm.forward(x);
+        }
+        if let Some(norm) = &self.layer_norm {
+            x = norm.forward(x);
+        }
+        let x = self.activation.forward(x);
+        let x = self.dropout.forward(x);
+
+        if self.residual {
+            x + input
+        } else {
+            x
+        }
+    }
+}
 
 #[derive(Module, Debug)]
 pub struct Model<B: Backend> {
-    input_layer: Linear<B>,
-    // hidden_layer: Linear<B>,
-    // second_hidden_layer: Linear<B>,
+    /// One table per categorical column, empty for the variant that only sees one-hots
+    embeddings: Vec<Embedding<B>>,
+    hidden_layers: Vec<HiddenLayer<B>>,
     output_layer: Linear<B>,
-    dropout: Dropout,
-    activation: Relu,
+    head: Head,
+}
+
+/// P(Transported) from the logits `Model::forward` returns
+pub fn transported_probability<B: Backend>(logits: Tensor<B, 2>) -> Tensor<B, 1> {
+    let [batch_size, _] = logits.dims();
+    let output = activation::softmax(logits, 1);
+
+    output.slice([0..batch_size, 1..2]).flatten(0, 1)
 }
 
 impl<B: Backend> Model<B> {
-    pub fn forward(&self, input: Tensor<B, 2>) -> Tensor<B, 2> {
-        let x = input.detach();
+    /// Logits of "not transported" and "transported". The sigmoid head's "not transported" logit is always 0, so a
+    /// softmax over the two gives the sigmoid of its single logit.
+    pub fn forward(&self, input: Tensor<B, 2>, categories: Tensor<B, 2, Int>) -> Tensor<B, 2> {
+        let mut x = self.embed(input, categories);
+
+        for layer in self.hidden_layers.iter() {
+            x = layer.forward(x);
+        }
 
-        let x = self.input_layer.forward(x);
-        let x = self.dropout.forward(x);
-        let x = self.activation.forward(x);
-        // let x = self.hidden_layer.forward(x);
-        // let x = self.dropout.forward(x);
-        // let x = self.activation.forward(x);
-        // let x = self.second_hidden_layer.forward(x);
-        // let x = self.dropout.forward(x);
-        // let x = self.activation.forward(x);
         let x = self.output_layer.forward(x);
+        match self.head {
+            Head::Softmax => x,
+            Head::Sigmoid { .. } => Tensor::cat(vec![x.zeros_like(), x], 1),
+        }
+    }
+
+    /// Whether any hidden layer normalizes with batch norm, whose output on an autodiff backend depends on the whole
+    /// batch
+    pub fn uses_batch_norm(&self) -> bool {
+        self.hidden_layers
+            .iter()
+            .any(|layer| layer.batch_norm.is_some())
+    }
 
-        x
+    /// P(Transported) of every passenger
+    pub fn probabilities(
+        &self,
+        input: Tensor<B, 2>,
+        categories: Tensor<B, 2, Int>,
+    ) -> Tensor<B, 1> {
+        transported_probability(self.forward(input, categories))
+    }
+
+    /// Looks up every categorical column in its embedding table and appends the embeddings to the inputs
+    fn embed(&self, inputs: Tensor<B, 2>, categories: Tensor<B, 2, Int>) -> Tensor<B, 2> {
+        if self.embeddings.is_empty() {
+            return inputs;
+        }
+
+        let [batch_size, _] = inputs.dims();
+        let mut x = vec![inputs];
+        for (column, embedding) in self.embeddings.iter().enumerate() {
+            let indices = categories
+                .clone()
+                .slice([0..batch_size, column..column + 1]);
+            let embedded = embedding.forward(indices);
+            let [_, _, size] = embedded.dims();
+            x.push(embedded.reshape([batch_size, size]));
+        }
+
+        Tensor::cat(x, 1)
     }
 
     pub fn forward_step(&self, item: TitanicBatch<B>) -> ClassificationOutput<B> {
         let targets = item.targets.unsqueeze();
-        let output = self.forward(item.inputs);
+        let output = self.forward(item.inputs, item.categories);
 
-        let loss = CrossEntropyLossConfig::new()
-            .init(&output.device())
-            .forward(output.clone(), targets.clone());
+        let loss = match self.head {
+            Head::Softmax => CrossEntropyLossConfig::new()
+                .init(&output.device())
+                .forward(output.clone(), targets.clone()),
+            Head::Sigmoid {
+                label_smoothing,
+                positive_weight,
+            } => {
+                let [batch_size, _] = output.dims();
+                BinaryCrossEntropyLossConfig::new()
+                    .with_logits(true)
+                    .with_smoothing((label_smoothing > 0.0).then_some(label_smoothing))
+                    .with_weights(Some(vec![1.0, positive_weight]))
+                    .init(&output.device())
+                    .forward(
+                        output.clone().slice([0..batch_size, 1..2]).flatten(0, 1),
+                        targets.clone(),
+                    )
+            }
+        };
 
         ClassificationOutput::new(loss, output, targets)
     }
 }
 
+/// One hidden layer of the model
 #[derive(Config, Debug)]
-pub struct ModelConfig {
-    #[config(default = 39)]
-    num_features: usize,
-    #[config(default = 28)]
-    hidden_size: usize,
+pub struct HiddenLayerConfig {
+    size: usize,
+    #[config(default = "Activation::Relu")]
+    activation: Activation,
+    #[config(default = "Normalization::None")]
+    normalization: Normalization,
     #[config(default = "0.35")]
     dropout: f64,
 }
 
-impl ModelConfig {
-    /// Returns the initialized model
-    pub fn init<B: Backend>(&self, device: &B::Device) -> Model<B> {
-        Model {
-            // input_layer: LinearConfig::new(self.num_features, self.num_features * 2)
-            //     .with_bias(true)
-            //     .init(device),
-            // hidden_layer: LinearConfig::new(self.num_features * 2, self.hidden_size)
-            //     .with_bias(true)
-            //     .init(device),
-            // second_hidden_layer: LinearConfig::new(self.hidden_size, self.hidden_size / 2)
-            //     .with_bias(true)
-            //     .init(device),
-            // output_layer: LinearConfig::new(self.hidden_size / 2, 2)
-            //     .with_bias(true)
-            //     .init(device),
-            // activation: Relu::new(),
-            // dropout: DropoutConfig::new(self.dropout).init(),
-            input_layer: LinearConfig::new(self.num_features, self.hidden_size)
+impl HiddenLayerConfig {
+    fn init<B: Backend>(
+        &self,
+        input_size: usize,
+        residual: bool,
+        device: &B::Device,
+    ) -> HiddenLayer<B> {
+        HiddenLayer {
+            linear: LinearConfig::new(input_size, self.size)
                 .with_bias(true)
                 .init(device),
-            output_layer: LinearConfig::new(self.hidden_size, 2)
+            batch_norm: (self.normalization == Normalization::Batch)
+                .then(|| BatchNormConfig::new(self.size).init(device)),
+            layer_norm: (self.normalization == Normalization::Layer)
+                .then(|| LayerNormConfig::new(self.size).init(device)),
+            activation: self.activation,
+            dropout: DropoutConfig::new(self.dropout).init(),
+            residual: residual && input_size == self.size,
+        }
+    }
+}
+
+#[derive(Config, Debug)]
+pub struct ModelConfig {
+    /// The hidden layers in order, the output layer comes after the last one
+    #[config(default = "vec![HiddenLayerConfig::new(28)]")]
+    hidden_layers: Vec<HiddenLayerConfig>,
+    /// Add the input of every hidden layer to its output, on the layers whose input is as wide as their output
+    #[config(default = false)]
+    residual: bool,
+    /// Size of the learned embedding of every categorical column, `None` for the variant that only sees one-hots.
+    /// The embeddings are added to the inputs, so the pipeline can leave out the one-hots they replace.
+    #[config(default = "None")]
+    embedding_size: Option<usize>,
+    #[config(default = "Head::Softmax")]
+    head: Head,
+}
+
+impl ModelConfig {
+    /// The same architecture with every dropout rate set to 0. Dropout stays active on autodiff backends, so models
+    /// that need gradients outside of training are built from this.
+    pub fn without_dropout(&self) -> Self {
+        let mut config = self.clone();
+        for layer in config.hidden_layers.iter_mut() {
+            layer.dropout = 0.0;
+        }
+        config
+    }
+
+    /// Returns the initialized model for inputs `num_features` wide, which comes from the feature schema
+    pub fn init<B: Backend>(&self, num_features: usize, device: &B::Device) -> Model<B> {
+        let embeddings: Vec<Embedding<B>> = match self.embedding_size {
+            Some(size) => CATEGORY_COUNTS
+                .iter()
+                .map(|count| EmbeddingConfig::new(*count, size).init(device))
+                .collect(),
+            None => Vec::new(),
+        };
+
+        let mut size = num_features + embeddings.len() * self.embedding_size.unwrap_or_default();
+        let mut hidden_layers = Vec::with_capacity(self.hidden_layers.len());
+        for layer in self.hidden_layers.iter() {
+            hidden_layers.push(layer.init(size, self.residual, device));
+            size = layer.size;
+        }
+
+        Model {
+            embeddings,
+            hidden_layers,
+            output_layer: LinearConfig::new(size, self.head.num_logits())
                 .with_bias(true)
                 .init(device),
-            activation: Relu::new(),
-            dropout: DropoutConfig::new(self.dropout).init(),
+            head: self.head,
         }
     }
 }
@@ -0,0 +1,47 @@
+use std::collections::HashMap;
+
+use crate::dataset::TitanicItemRaw;
+
+/// Surname counts over every passenger we know of, overall and within each group
+#[derive(Debug, Clone, Default)]
+pub struct NameStats {
+    /// Passengers with each surname
+    surnames: HashMap<String, usize>,
+    /// Passengers with each surname in each group
+    group_surnames: HashMap<(u32, String), usize>,
+}
+
+impl NameStats {
+    pub fn new(rows: &[TitanicItemRaw]) -> Self {
+        let mut stats = Self::default();
+
+        for item in rows.iter() {
+            let Some(surname) = item.surname() else {
+                continue;
+            };
+
+            *stats.surnames.entry(surname.to_string()).or_default() += 1;
+            if let Some(group) = item.group_number() {
+                *stats
+                    .group_surnames
+                    .entry((group, surname.to_string()))
+                    .or_default() += 1;
+            }
+        }
+
+        stats
+    }
+
+    /// How many passengers share `surname`, including the passenger themself
+    pub fn surname_frequency(&self, surname: &str) -> usize {
+        self.surnames.get(surname).copied().unwrap_or(1)
+    }
+
+    /// How many passengers of `group` share `surname`, which is our best guess at the size of their family on board
+    pub fn family_size(&self, group: u32, surname: &str) -> usize {
+        self.group_surnames
+            .get(&(group, surname.to_string()))
+            .copied()
+            .unwrap_or(1)
+    }
+}
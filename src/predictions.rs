@@ -0,0 +1,90 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::error::SubmissionError;
+
+/// File format of the predictions export
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PredictionsFormat {
+    #[default]
+    Csv,
+    /// One JSON object per line
+    #[value(name = "jsonl")]
+    JsonLines,
+}
+
+/// Everything the model said about one passenger, for stacking and error analysis
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct PredictionRow {
+    pub passenger_id: String,
+    /// P(Transported)
+    pub probability: f64,
+    /// Raw logits from `Model::forward`, the sigmoid head's "not transported" logit is always 0
+    pub logit_not_transported: f64,
+    pub logit_transported: f64,
+    /// The predicted label, whether `probability` is above `threshold`
+    pub transported: bool,
+    pub threshold: f64,
+    /// Which model made the prediction, the artifact directory unless given on the command line
+    pub model: String,
+    /// Cross-validation fold of the model, when it was saved to a `fold-<n>` directory
+    pub fold: Option<usize>,
+}
+
+/// The fold a model was trained on, from the `fold-<n>` directory cross-validation saves it to
+pub fn fold_of(artifact_dir: &str) -> Option<usize> {
+    Path::new(artifact_dir)
+        .file_name()?
+        .to_str()?
+        .strip_prefix("fold-")?
+        .parse()
+        .ok()
+}
+
+/// Writes `rows` to `path` in `format`
+pub fn write_predictions(
+    path: &Path,
+    format: PredictionsFormat,
+    rows: &[PredictionRow],
+) -> Result<(), SubmissionError> {
+    let io_error = |source| SubmissionError::Io {
+        path: path.to_path_buf(),
+        source,
+    };
+    let file = File::create(path).map_err(io_error)?;
+
+    match format {
+        PredictionsFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(file);
+            for row in rows.iter() {
+                writer
+                    .serialize(row)
+                    .map_err(|source| SubmissionError::Csv {
+                        path: path.to_path_buf(),
+                        source,
+                    })?;
+            }
+            writer.flush().map_err(io_error)
+        }
+        PredictionsFormat::JsonLines => {
+            let mut writer = BufWriter::new(file);
+            for row in rows.iter() {
+                serde_json::to_writer(&mut writer, row).map_err(|source| {
+                    SubmissionError::Json {
+                        path: path.to_path_buf(),
+                        source,
+                    }
+                })?;
+                writer.write_all(b"\n").map_err(io_error)?;
+            }
+            writer.flush().map_err(io_error)
+        }
+    }
+}
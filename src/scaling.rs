@@ -0,0 +1,170 @@
+use std::collections::BTreeMap;
+
+use burn::config::Config;
+use serde::{Deserialize, Serialize};
+
+use crate::dataset::TitanicItem;
+use crate::features::{FeatureKind, FeatureSchema};
+
+/// How a numeric feature is rescaled before it goes into the model
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scaling {
+    /// Leave the value as it is
+    None,
+    /// Map the training range onto 0 to 1
+    MinMax,
+    /// Subtract the mean and divide by the standard deviation
+    ZScore,
+    /// Subtract the median and divide by the interquartile range, which outliers barely move
+    Robust,
+    /// Take `ln(1 + x)` and z-score the result, for heavy tailed values like spending
+    Log1p,
+}
+
+/// Which scaling every numeric feature of the schema gets, one-hot features are never scaled
+#[derive(Config, Debug)]
+pub struct ScalerConfig {
+    /// Scaling of the numeric features that have no entry in `features`
+    #[config(default = "Scaling::ZScore")]
+    pub default: Scaling,
+    /// Scaling of single features, keyed by their schema name
+    #[config(default = "default_feature_scaling()")]
+    pub features: BTreeMap<String, Scaling>,
+}
+
+/// Spending is heavy tailed, so every spending feature of the default pipeline is log scaled
+fn default_feature_scaling() -> BTreeMap<String, Scaling> {
+    [
+        "TotalSpending",
+        "Affluence",
+        "GroupSpending",
+        "GroupMeanSpending",
+    ]
+    .into_iter()
+    .map(|name| (name.to_string(), Scaling::Log1p))
+    .collect()
+}
+
+/// The fitted scaling of one input column, the column becomes `(x - offset) / scale` after the optional log
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct ColumnScale {
+    log1p: bool,
+    offset: f32,
+    scale: f32,
+}
+
+impl ColumnScale {
+    const IDENTITY: Self = Self {
+        log1p: false,
+        offset: 0.0,
+        scale: 1.0,
+    };
+
+    fn fit(scaling: Scaling, values: &mut [f32]) -> Self {
+        if values.is_empty() {
+            return Self::IDENTITY;
+        }
+
+        let log1p = scaling == Scaling::Log1p;
+        if log1p {
+            values
+                .iter_mut()
+                .for_each(|value| *value = value.max(0.0).ln_1p());
+        }
+
+        let count = values.len() as f32;
+        let (offset, scale) = match scaling {
+            Scaling::None => return Self::IDENTITY,
+            Scaling::MinMax => {
+                let min = values.iter().copied().fold(f32::INFINITY, f32::min);
+                let max = values.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+                (min, max - min)
+            }
+            Scaling::ZScore | Scaling::Log1p => {
+                let mean = values.iter().sum::<f32>() / count;
+                let variance = values
+                    .iter()
+                    .map(|value| (value - mean).powi(2))
+                    .sum::<f32>()
+                    / count;
+                (mean, variance.sqrt())
+            }
+            Scaling::Robust => {
+                values.sort_by(f32::total_cmp);
+                let quantile = |q: f32| values[((values.len() - 1) as f32 * q).round() as usize];
+                (quantile(0.5), quantile(0.75) - quantile(0.25))
+            }
+        };
+
+        Self {
+            log1p,
+            offset: if offset.is_finite() { offset } else { 0.0 },
+            // A constant column would divide by zero, so it is only shifted
+            scale: if scale.is_finite() && scale > 0.0 {
+                scale
+            } else {
+                1.0
+            },
+        }
+    }
+
+    fn apply(&self, value: f32) -> f32 {
+        let value = if self.log1p {
+            value.max(0.0).ln_1p()
+        } else {
+            value
+        };
+
+        (value - self.offset) / self.scale
+    }
+}
+
+impl ScalerConfig {
+    /// Fits the scaling of every input column on `items`
+    pub fn fit(&self, schema: &FeatureSchema, items: &[TitanicItem]) -> FittedScaler {
+        let num_features = schema.num_features();
+        let mut inputs = Vec::with_capacity(items.len() * num_features);
+        for item in items.iter() {
+            schema.encode(item, &mut inputs);
+        }
+
+        let mut columns = Vec::with_capacity(num_features);
+        for feature in schema.features.iter() {
+            let scaling = match feature.kind {
+                FeatureKind::Numeric => *self.features.get(&feature.name).unwrap_or(&self.default),
+                FeatureKind::OneHot => Scaling::None,
+            };
+
+            for _ in 0..feature.width {
+                let column = columns.len();
+                let mut values: Vec<f32> = inputs
+                    .iter()
+                    .skip(column)
+                    .step_by(num_features)
+                    .copied()
+                    .collect();
+                columns.push(ColumnScale::fit(scaling, &mut values));
+            }
+        }
+
+        FittedScaler::new(columns)
+    }
+}
+
+/// Offset and scale of every input column, as fitted by `ScalerConfig::fit`
+#[derive(Config, Debug)]
+pub struct FittedScaler {
+    /// The scaling of every input column, in schema order
+    pub columns: Vec<ColumnScale>,
+}
+
+impl FittedScaler {
+    /// Rescales `inputs`, one or more rows as wide as the schema the scaler was fitted with
+    pub fn apply(&self, inputs: &mut [f32]) {
+        for row in inputs.chunks_mut(self.columns.len()) {
+            for (value, scale) in row.iter_mut().zip(self.columns.iter()) {
+                *value = scale.apply(*value);
+            }
+        }
+    }
+}
@@ -0,0 +1,245 @@
+use std::collections::BTreeMap;
+
+use burn::config::Config;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+
+use crate::dataset::TitanicItemRaw;
+
+/// How the training CSV is split into training and validation rows
+#[derive(Config, Debug)]
+pub struct SplitConfig {
+    /// Share of the rows kept back for validation
+    #[config(default = 0.1)]
+    pub valid_ratio: f64,
+    /// Keep the share of transported passengers the same in both splits
+    #[config(default = true)]
+    pub stratify: bool,
+    /// Keep every passenger of a group in the same split, so group-mates can't leak across it
+    #[config(default = true)]
+    pub group_aware: bool,
+}
+
+impl SplitConfig {
+    /// Groups the indices of `rows` into the units that have to stay in one split, bucketed by whether the unit
+    /// counts as transported. Without stratification everything lands in a single bucket.
+    fn strata(&self, rows: &[TitanicItemRaw]) -> BTreeMap<bool, Vec<Vec<usize>>> {
+        // The rows that have to stay together, keyed so their order doesn't depend on hashing
+        let mut units: BTreeMap<(u32, usize), Vec<usize>> = BTreeMap::new();
+        for (index, item) in rows.iter().enumerate() {
+            let key = match item.group_number() {
+                Some(group) if self.group_aware => (group, 0),
+                _ => (u32::MAX, index),
+            };
+            units.entry(key).or_default().push(index);
+        }
+
+        // A group counts as transported when most of its passengers were
+        let mut strata: BTreeMap<bool, Vec<Vec<usize>>> = BTreeMap::new();
+        for unit in units.into_values() {
+            let transported = unit
+                .iter()
+                .filter(|index| rows[**index].transported.as_deref() == Some("True"))
+                .count();
+            let stratum = self.stratify && transported * 2 >= unit.len();
+            strata.entry(stratum).or_default().push(unit);
+        }
+
+        strata
+    }
+
+    /// Shuffles `rows` with a generator seeded from `seed` and splits them into training and validation rows. Both
+    /// splits keep the file order of their rows.
+    pub fn split(
+        &self,
+        rows: Vec<TitanicItemRaw>,
+        seed: u64,
+    ) -> (Vec<TitanicItemRaw>, Vec<TitanicItemRaw>) {
+        assert!(
+            self.valid_ratio > 0.0 && self.valid_ratio < 1.0,
+            "The validation ratio must be between 0 and 1, got {}",
+            self.valid_ratio
+        );
+
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut is_valid = vec![false; rows.len()];
+        for mut units in self.strata(&rows).into_values() {
+            units.shuffle(&mut rng);
+
+            let total: usize = units.iter().map(Vec::len).sum();
+            let target = (total as f64 * self.valid_ratio).round() as usize;
+            let mut taken = 0;
+            for unit in units.iter() {
+                if taken >= target {
+                    break;
+                }
+                taken += unit.len();
+                for index in unit {
+                    is_valid[*index] = true;
+                }
+            }
+        }
+
+        let (valid, train): (Vec<_>, Vec<_>) = rows
+            .into_iter()
+            .zip(is_valid)
+            .partition(|(_, is_valid)| *is_valid);
+
+        (
+            train.into_iter().map(|(item, _)| item).collect(),
+            valid.into_iter().map(|(item, _)| item).collect(),
+        )
+    }
+
+    /// Shuffles `rows` with a generator seeded from `seed` and deals them into `k` folds of about the same size,
+    /// returning the training and validation rows of every fold. The validation ratio isn't used, each fold keeps
+    /// back `1 / k` of the rows.
+    pub fn folds(
+        &self,
+        rows: &[TitanicItemRaw],
+        k: usize,
+        seed: u64,
+    ) -> Vec<(Vec<TitanicItemRaw>, Vec<TitanicItemRaw>)> {
+        assert!(k >= 2, "Cross validation needs at least 2 folds, got {k}");
+
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut fold_of = vec![0; rows.len()];
+        let mut sizes = vec![0; k];
+        for mut units in self.strata(rows).into_values() {
+            units.shuffle(&mut rng);
+
+            // Every unit goes to the fold of its stratum with the fewest rows so far, ties go to the fold with the
+            // fewest rows overall so no fold ends up the largest in every stratum
+            let mut stratum_sizes = vec![0; k];
+            for unit in units.iter() {
+                let fold = (0..k)
+                    .min_by_key(|fold| (stratum_sizes[*fold], sizes[*fold]))
+                    .expect("There is at least one fold");
+                stratum_sizes[fold] += unit.len();
+                sizes[fold] += unit.len();
+                for index in unit {
+                    fold_of[*index] = fold;
+                }
+            }
+        }
+
+        (0..k)
+            .map(|fold| {
+                let (valid, train): (Vec<_>, Vec<_>) = rows
+                    .iter()
+                    .zip(fold_of.iter())
+                    .partition(|(_, of)| **of == fold);
+
+                (
+                    train.into_iter().map(|(item, _)| item.clone()).collect(),
+                    valid.into_iter().map(|(item, _)| item.clone()).collect(),
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    /// Groups of one to three passengers, every fourth group transported
+    fn rows() -> Vec<TitanicItemRaw> {
+        let mut rows = Vec::new();
+        for group in 1..=300 {
+            for passenger in 1..=(group % 3 + 1) {
+                rows.push(TitanicItemRaw {
+                    passenger_id: format!("{group:04}_{passenger:02}"),
+                    home_planet: "Earth".to_string(),
+                    cryo_sleep: None,
+                    cabin: None,
+                    destination: "TRAPPIST-1e".to_string(),
+                    age: None,
+                    vip: None,
+                    room_service: None,
+                    food_court: None,
+                    shopping_mall: None,
+                    spa: None,
+                    vr_deck: None,
+                    name: None,
+                    transported: Some(if group % 4 == 0 { "True" } else { "False" }.to_string()),
+                    row: 0,
+                });
+            }
+        }
+        rows
+    }
+
+    fn groups(rows: &[TitanicItemRaw]) -> HashSet<u32> {
+        rows.iter().filter_map(|item| item.group_number()).collect()
+    }
+
+    fn transported(rows: &[TitanicItemRaw]) -> usize {
+        rows.iter()
+            .filter(|item| item.transported.as_deref() == Some("True"))
+            .count()
+    }
+
+    #[test]
+    fn groups_stay_in_one_split() {
+        let (train, valid) = SplitConfig::new().split(rows(), 42);
+        assert!(groups(&train).is_disjoint(&groups(&valid)));
+
+        for (train, valid) in SplitConfig::new().folds(&rows(), 5, 42) {
+            assert!(groups(&train).is_disjoint(&groups(&valid)));
+        }
+    }
+
+    #[test]
+    fn split_keeps_the_ratio_within_each_stratum() {
+        let rows = rows();
+        let (train, valid) = SplitConfig::new()
+            .with_valid_ratio(0.2)
+            .split(rows.clone(), 42);
+        assert_eq!(train.len() + valid.len(), rows.len());
+
+        // A stratum can only overshoot its target by less than one group of at most three passengers
+        let total_transported = transported(&rows);
+        let total_stayed = rows.len() - total_transported;
+        let valid_transported = transported(&valid);
+        let valid_stayed = valid.len() - valid_transported;
+        let target = |total: usize| (total as f64 * 0.2).round() as usize;
+        assert!(
+            (target(total_transported)..target(total_transported) + 3).contains(&valid_transported)
+        );
+        assert!((target(total_stayed)..target(total_stayed) + 3).contains(&valid_stayed));
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_split() {
+        let ids = |rows: Vec<TitanicItemRaw>| -> Vec<String> {
+            rows.into_iter().map(|item| item.passenger_id).collect()
+        };
+
+        let (_, first) = SplitConfig::new().split(rows(), 7);
+        let (_, second) = SplitConfig::new().split(rows(), 7);
+        let (_, other) = SplitConfig::new().split(rows(), 8);
+        assert_eq!(ids(first.clone()), ids(second));
+        assert_ne!(ids(first), ids(other));
+    }
+
+    #[test]
+    fn folds_cover_every_row_once_and_are_balanced() {
+        let rows = rows();
+        let folds = SplitConfig::new().folds(&rows, 5, 42);
+
+        let sizes: Vec<usize> = folds.iter().map(|(_, valid)| valid.len()).collect();
+        assert_eq!(sizes.iter().sum::<usize>(), rows.len());
+        for (train, valid) in folds.iter() {
+            assert_eq!(train.len() + valid.len(), rows.len());
+        }
+
+        // Each stratum leaves its folds at most one group apart, so two strata at most two groups
+        let largest = sizes.iter().max().unwrap();
+        let smallest = sizes.iter().min().unwrap();
+        assert!(largest - smallest <= 6, "unbalanced folds {sizes:?}");
+    }
+}
@@ -0,0 +1,83 @@
+use std::{collections::BTreeSet, fs::File, path::Path};
+
+use serde::Serialize;
+
+use crate::error::SubmissionError;
+
+/// One row of the Kaggle submission
+#[derive(Serialize, Debug, Clone)]
+pub struct SubmissionRow {
+    #[serde(rename = "PassengerId")]
+    pub passenger_id: String,
+    /// `True`/`False`, or P(Transported) when probabilities are written
+    #[serde(rename = "Transported")]
+    pub transported: String,
+}
+
+fn csv_error(path: &Path) -> impl Fn(csv::Error) -> SubmissionError + '_ {
+    |source| SubmissionError::Csv {
+        path: path.to_path_buf(),
+        source,
+    }
+}
+
+/// Writes `rows` to `path` as a `PassengerId,Transported` CSV
+pub fn write_submission(path: &Path, rows: &[SubmissionRow]) -> Result<(), SubmissionError> {
+    let file = File::create(path).map_err(|source| SubmissionError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+
+    let mut writer = csv::Writer::from_writer(file);
+    for row in rows.iter() {
+        writer.serialize(row).map_err(csv_error(path))?;
+    }
+    writer.flush().map_err(|source| SubmissionError::Io {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// Every `PassengerId` of the CSV at `path`, in file order
+pub fn read_passenger_ids(path: &Path) -> Result<Vec<String>, SubmissionError> {
+    let mut reader = csv::Reader::from_path(path).map_err(csv_error(path))?;
+    let column = reader
+        .headers()
+        .map_err(csv_error(path))?
+        .iter()
+        .position(|header| header == "PassengerId")
+        .ok_or_else(|| SubmissionError::MissingIdColumn {
+            path: path.to_path_buf(),
+        })?;
+
+    let mut ids = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(csv_error(path))?;
+        ids.push(record.get(column).unwrap_or_default().to_string());
+    }
+
+    Ok(ids)
+}
+
+/// Checks that `rows` hold exactly the passengers of the sample submission at `sample`, so a bad submission is never
+/// written
+pub fn check_submission(rows: &[SubmissionRow], sample: &Path) -> Result<(), SubmissionError> {
+    let expected = read_passenger_ids(sample)?;
+    if rows.len() != expected.len() {
+        return Err(SubmissionError::RowCount {
+            expected: expected.len(),
+            found: rows.len(),
+        });
+    }
+
+    let ids: BTreeSet<&str> = rows.iter().map(|row| row.passenger_id.as_str()).collect();
+    let expected: BTreeSet<&str> = expected.iter().map(String::as_str).collect();
+    if ids != expected {
+        return Err(SubmissionError::PassengerIds {
+            missing: expected.difference(&ids).map(|id| id.to_string()).collect(),
+            unexpected: ids.difference(&expected).map(|id| id.to_string()).collect(),
+        });
+    }
+
+    Ok(())
+}
@@ -0,0 +1,76 @@
+use burn::config::Config;
+use serde::{Deserialize, Serialize};
+
+/// What the decision threshold is tuned for
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ThresholdMetric {
+    Accuracy,
+    /// F1 score of the transported class
+    F1,
+    /// Mean of the recall of both classes
+    BalancedAccuracy,
+}
+
+impl ThresholdMetric {
+    /// The metric of predicting `predictions` for passengers whose real outcome is `labels`
+    fn score(&self, predictions: impl Iterator<Item = bool>, labels: &[bool]) -> f64 {
+        let (mut true_pos, mut false_pos, mut true_neg, mut false_neg) = (0.0, 0.0, 0.0, 0.0);
+        for (predicted, label) in predictions.zip(labels.iter()) {
+            match (predicted, label) {
+                (true, true) => true_pos += 1.0,
+                (true, false) => false_pos += 1.0,
+                (false, false) => true_neg += 1.0,
+                (false, true) => false_neg += 1.0,
+            }
+        }
+
+        let ratio = |num: f64, den: f64| if den > 0.0 { num / den } else { 0.0 };
+        match self {
+            ThresholdMetric::Accuracy => ratio(
+                true_pos + true_neg,
+                true_pos + false_pos + true_neg + false_neg,
+            ),
+            ThresholdMetric::F1 => ratio(2.0 * true_pos, 2.0 * true_pos + false_pos + false_neg),
+            ThresholdMetric::BalancedAccuracy => {
+                (ratio(true_pos, true_pos + false_neg) + ratio(true_neg, true_neg + false_pos))
+                    / 2.0
+            }
+        }
+    }
+}
+
+/// The P(Transported) above which a passenger is predicted as transported, tuned on the validation split and saved
+/// next to the model
+#[derive(Config, Debug)]
+pub struct DecisionThreshold {
+    pub metric: ThresholdMetric,
+    pub threshold: f64,
+    /// The metric on the validation split at `threshold`
+    pub score: f64,
+}
+
+impl DecisionThreshold {
+    /// Tries thresholds from 0.01 to 0.99 and keeps the one with the best `metric`. Of equally good thresholds the one
+    /// closest to 0.5 wins, so a flat metric leaves the plain argmax decision.
+    pub fn sweep(metric: ThresholdMetric, probabilities: &[f64], labels: &[bool]) -> Self {
+        let mut best = Self::new(metric, 0.5, 0.0);
+        for step in 1..100 {
+            let threshold = step as f64 / 100.0;
+            let predictions = probabilities
+                .iter()
+                .map(|probability| *probability > threshold);
+            let score = metric.score(predictions, labels);
+
+            let closer = (threshold - 0.5).abs() < (best.threshold - 0.5).abs();
+            if score > best.score || (score == best.score && closer) {
+                best = Self::new(metric, threshold, score);
+            }
+        }
+
+        best
+    }
+
+    pub fn decide(&self, probability: f64) -> bool {
+        probability > self.threshold
+    }
+}
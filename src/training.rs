@@ -1,23 +1,36 @@
-use crate::{data::TitanicBatcher, dataset::TitanicDataset, model::ModelConfig};
+use std::path::Path;
+
+use crate::{
+    cabins::CabinConfig,
+    cli::{CrossValidateArgs, TrainArgs},
+    data::TitanicBatcher,
+    dataset::{TitanicDataset, TitanicItem},
+    features::{FeaturePipeline, FeatureSchema},
+    impute::{FittedImputation, ImputationConfig},
+    inference::{probabilities, score, Score},
+    model::{Model, ModelConfig},
+    scaling::{FittedScaler, ScalerConfig},
+    split::SplitConfig,
+    threshold::{DecisionThreshold, ThresholdMetric},
+};
 use burn::train::metric::store::{Aggregate, Direction, Split};
+#[cfg(feature = "cuda")]
 use burn::train::metric::CudaMetric;
 use burn::train::MetricEarlyStoppingStrategy;
 use burn::train::StoppingCondition;
 use burn::{config::Config, data::dataset::Dataset, record::NoStdTrainingRecorder};
 use burn::{
     data::dataloader::DataLoaderBuilder,
-    module::Module,
+    module::{AutodiffModule, Module},
     optim::AdamConfig,
     record::CompactRecorder,
-    tensor::backend::AutodiffBackend,
+    tensor::backend::{AutodiffBackend, Backend},
     train::{
         metric::{AccuracyMetric, LossMetric},
         LearnerBuilder,
     },
 };
 
-const ARTIFACT_DIR: &'static str = "/tmp/titanic";
-
 #[derive(Config)]
 pub struct TrainingConfig {
     pub model: ModelConfig,
@@ -32,21 +45,131 @@ pub struct TrainingConfig {
     pub seed: u64,
     #[config(default = 1.0e-4)]
     pub learning_rate: f64,
+    #[config(default = "SplitConfig::new()")]
+    pub split: SplitConfig,
+    #[config(default = "ImputationConfig::new()")]
+    pub imputation: ImputationConfig,
+    #[config(default = "CabinConfig::new()")]
+    pub cabins: CabinConfig,
+    #[config(default = "FeaturePipeline::titanic()")]
+    pub features: FeaturePipeline,
+    #[config(default = "ScalerConfig::new()")]
+    pub scaling: ScalerConfig,
+    /// Metric the decision threshold is tuned for on the validation split
+    #[config(default = "ThresholdMetric::Accuracy")]
+    pub threshold_metric: ThresholdMetric,
+}
+
+impl TrainingConfig {
+    /// Loads the config at `path`, or builds the default one when no path is given
+    pub fn load_or_default(path: Option<&Path>) -> Self {
+        match path {
+            Some(path) => TrainingConfig::load(path).unwrap_or_else(|err| {
+                panic!(
+                    "Failed to load the training config {}: {err}",
+                    path.display()
+                )
+            }),
+            None => {
+                TrainingConfig::new(ModelConfig::new(), AdamConfig::new()).with_num_epochs(5000)
+            }
+        }
+    }
 }
 
-pub fn run<B: AutodiffBackend>(device: B::Device) {
-    let optimizer = AdamConfig::new();
-    let config = TrainingConfig::new(ModelConfig::new(), optimizer).with_num_epochs(5000);
+pub fn run<B: AutodiffBackend>(args: &TrainArgs, device: B::Device) {
+    let artifact_dir = args.run.artifact_dir.as_str();
+    let paths = args.run.data.paths();
+    let policy = args.run.data.bad_rows;
+
+    let config = TrainingConfig::load_or_default(args.config.as_deref());
     B::seed(config.seed);
 
-    let train_dataset = TitanicDataset::train();
-    let test_dataset = TitanicDataset::test();
+    let (train_dataset, test_dataset, imputation) = TitanicDataset::train_valid(
+        &paths,
+        policy,
+        &config.split,
+        &config.imputation,
+        &config.cabins,
+        config.seed,
+    )
+    .unwrap_or_else(|err| panic!("Failed to load the training data: {err}"));
 
     println!("Train data is {} entries", train_dataset.len());
     println!("Test data is {} entries", test_dataset.len());
+    println!(
+        "Imputed training values:\n{}",
+        train_dataset.imputation_report()
+    );
+
+    let schema = FeatureSchema::from_pipeline(config.features.clone());
+    let valid_items: Vec<TitanicItem> = test_dataset.iter().collect();
+    let scaler = fit_scaler(&config, &schema, &train_dataset);
+    let model_trained = fit::<B>(
+        &config,
+        &schema,
+        &scaler,
+        artifact_dir,
+        train_dataset,
+        test_dataset,
+        &device,
+    );
+
+    let batcher = TitanicBatcher::new(device, schema.clone(), scaler.clone());
+    let threshold = tune_threshold(&config, &model_trained.valid(), &batcher, valid_items);
+    println!(
+        "Decision threshold: {:.2} ({:?} {:.4})",
+        threshold.threshold, threshold.metric, threshold.score
+    );
+
+    save(
+        &config,
+        &imputation,
+        &schema,
+        &scaler,
+        &threshold,
+        model_trained,
+        artifact_dir,
+    );
+}
+
+/// Picks the decision threshold that maximizes the configured metric on `valid_items`
+fn tune_threshold<B: Backend>(
+    config: &TrainingConfig,
+    model: &Model<B>,
+    batcher: &TitanicBatcher<B>,
+    valid_items: Vec<TitanicItem>,
+) -> DecisionThreshold {
+    let labels: Vec<bool> = valid_items.iter().map(|item| item.transported).collect();
+    let probabilities = probabilities(model, batcher, valid_items);
 
-    let batcher_train = TitanicBatcher::<B>::new(device.clone());
-    let batcher_test = TitanicBatcher::<B::InnerBackend>::new(device.clone());
+    DecisionThreshold::sweep(config.threshold_metric, &probabilities, &labels)
+}
+
+/// Fits the input scaling on the training split only
+fn fit_scaler(
+    config: &TrainingConfig,
+    schema: &FeatureSchema,
+    train_dataset: &TitanicDataset,
+) -> FittedScaler {
+    let items: Vec<TitanicItem> = train_dataset.iter().collect();
+    config.scaling.fit(schema, &items)
+}
+
+/// Trains a fresh model on `train_dataset`, validating on `test_dataset` after every epoch. Checkpoints and the learner's logs go to
+/// `artifact_dir`.
+fn fit<B: AutodiffBackend>(
+    config: &TrainingConfig,
+    schema: &FeatureSchema,
+    scaler: &FittedScaler,
+    artifact_dir: &str,
+    train_dataset: TitanicDataset,
+    test_dataset: TitanicDataset,
+    device: &B::Device,
+) -> Model<B> {
+    let batcher_train = TitanicBatcher::<B>::new(device.clone(), schema.clone(), scaler.clone());
+    let batcher_test =
+        TitanicBatcher::<B::InnerBackend>::new(device.clone(), schema.clone(), scaler.clone());
 
     let dataloader_train = DataLoaderBuilder::new(batcher_train)
         .batch_size(config.batch_size)
@@ -61,13 +184,18 @@ pub fn run<B: AutodiffBackend>(device: B::Device) {
         .build(test_dataset);
 
     // Model
-    let learner = LearnerBuilder::new(ARTIFACT_DIR)
+    let builder = LearnerBuilder::new(artifact_dir)
         .metric_train_numeric(AccuracyMetric::new())
         .metric_valid_numeric(AccuracyMetric::new())
         .metric_train_numeric(LossMetric::new())
-        .metric_valid_numeric(LossMetric::new())
+        .metric_valid_numeric(LossMetric::new());
+
+    #[cfg(feature = "cuda")]
+    let builder = builder
         .metric_train(CudaMetric::new())
-        .metric_valid(CudaMetric::new())
+        .metric_valid(CudaMetric::new());
+
+    let learner = builder
         .with_file_checkpointer(CompactRecorder::new())
         // .early_stopping(MetricEarlyStoppingStrategy::new::<LossMetric<B>>(
         //     Aggregate::Mean,
@@ -79,18 +207,160 @@ pub fn run<B: AutodiffBackend>(device: B::Device) {
         .num_epochs(config.num_epochs)
         .summary()
         .build(
-            config.model.init::<B>(&device),
+            config.model.init::<B>(schema.num_features(), device),
             config.optimzer.init(),
             config.learning_rate,
         );
 
-    let model_trained = learner.fit(dataloader_train, dataloader_test);
+    learner.fit(dataloader_train, dataloader_test)
+}
 
+/// Saves the config, the fitted imputation and scaling, the feature schema, the decision threshold and the trained
+/// model so `infer` and `evaluate` can load them
+fn save<B: AutodiffBackend>(
+    config: &TrainingConfig,
+    imputation: &FittedImputation,
+    schema: &FeatureSchema,
+    scaler: &FittedScaler,
+    threshold: &DecisionThreshold,
+    model: Model<B>,
+    artifact_dir: &str,
+) {
     config
-        .save(format!("{ARTIFACT_DIR}/config.json").as_str())
+        .save(format!("{artifact_dir}/config.json").as_str())
         .unwrap();
+    imputation
+        .save(format!("{artifact_dir}/imputation.json").as_str())
+        .expect("Failed to save the fitted imputation");
+    schema
+        .save(format!("{artifact_dir}/schema.json").as_str())
+        .expect("Failed to save the feature schema");
+    scaler
+        .save(format!("{artifact_dir}/scaler.json").as_str())
+        .expect("Failed to save the fitted scaler");
+    threshold
+        .save(format!("{artifact_dir}/threshold.json").as_str())
+        .expect("Failed to save the decision threshold");
 
-    model_trained
-        .save_file(format!("{ARTIFACT_DIR}/model"), &CompactRecorder::new())
+    model
+        .save_file(format!("{artifact_dir}/model"), &CompactRecorder::new())
         .expect("Failed to save trained model");
 }
+
+/// Mean and standard deviation of `values`
+fn mean_std(values: &[f64]) -> (f64, f64) {
+    let count = values.len().max(1) as f64;
+    let mean = values.iter().sum::<f64>() / count;
+    let variance = values
+        .iter()
+        .map(|value| (value - mean).powi(2))
+        .sum::<f64>()
+        / count;
+
+    (mean, variance.sqrt())
+}
+
+pub fn cross_validate<B: AutodiffBackend>(args: &CrossValidateArgs, device: B::Device) {
+    let artifact_dir = args.run.artifact_dir.as_str();
+    let paths = args.run.data.paths();
+    let policy = args.run.data.bad_rows;
+
+    let config = TrainingConfig::load_or_default(args.config.as_deref());
+    B::seed(config.seed);
+
+    let folds = TitanicDataset::folds(
+        &paths,
+        policy,
+        &config.split,
+        &config.imputation,
+        &config.cabins,
+        args.folds,
+        config.seed,
+    )
+    .unwrap_or_else(|err| panic!("Failed to load the training data: {err}"));
+
+    let schema = FeatureSchema::from_pipeline(config.features.clone());
+    let mut scores = Vec::new();
+    for (fold, (train_dataset, valid_dataset, imputation)) in folds.into_iter().enumerate() {
+        println!(
+            "Fold {fold}: training on {} entries, validating on {}",
+            train_dataset.len(),
+            valid_dataset.len()
+        );
+
+        // Folds that aren't kept train in a scratch directory that is removed again, so only kept folds stay on disk
+        let fold_dir = if args.keep_models {
+            format!("{artifact_dir}/fold-{fold}")
+        } else {
+            format!("{artifact_dir}/fold-scratch")
+        };
+        let valid_items: Vec<TitanicItem> = valid_dataset.iter().collect();
+        let scaler = fit_scaler(&config, &schema, &train_dataset);
+        let model = fit::<B>(
+            &config,
+            &schema,
+            &scaler,
+            &fold_dir,
+            train_dataset,
+            valid_dataset,
+            &device,
+        );
+
+        let batcher = TitanicBatcher::new(device.clone(), schema.clone(), scaler.clone());
+        let threshold = tune_threshold(&config, &model.valid(), &batcher, valid_items.clone());
+        // The threshold is tuned on the same rows, so its accuracy is a little optimistic
+        let score = score(&model.valid(), &batcher, valid_items, &threshold);
+        println!(
+            "Fold {fold}: accuracy {:.2}%, at threshold {:.2} {:.2}%, loss {:.4}",
+            100.0 * score.accuracy,
+            threshold.threshold,
+            100.0 * score.threshold_accuracy,
+            score.loss
+        );
+        scores.push(score);
+
+        if args.keep_models {
+            save(
+                &config,
+                &imputation,
+                &schema,
+                &scaler,
+                &threshold,
+                model,
+                &fold_dir,
+            );
+        } else if let Err(err) = std::fs::remove_dir_all(&fold_dir) {
+            log::warn!("Failed to remove the scratch directory {fold_dir}: {err}");
+        }
+    }
+
+    println!(
+        "{:<8}{:>10}{:>12}{:>10}",
+        "Fold", "Accuracy", "Threshold", "Loss"
+    );
+    for (fold, score) in scores.iter().enumerate() {
+        println!(
+            "{fold:<8}{:>9.2}%{:>11.2}%{:>10.4}",
+            100.0 * score.accuracy,
+            100.0 * score.threshold_accuracy,
+            score.loss
+        );
+    }
+
+    let summarize =
+        |value: fn(&Score) -> f64| mean_std(&scores.iter().map(value).collect::<Vec<_>>());
+    let (accuracy, accuracy_std) = summarize(|score| score.accuracy);
+    let (threshold_accuracy, threshold_accuracy_std) = summarize(|score| score.threshold_accuracy);
+    let (loss, loss_std) = summarize(|score| score.loss);
+    println!(
+        "Accuracy: {:.2}% ± {:.2}%",
+        100.0 * accuracy,
+        100.0 * accuracy_std
+    );
+    println!(
+        "Accuracy at the tuned thresholds: {:.2}% ± {:.2}% (in-sample)",
+        100.0 * threshold_accuracy,
+        100.0 * threshold_accuracy_std
+    );
+    println!("Loss: {loss:.4} ± {loss_std:.4}");
+}
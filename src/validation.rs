@@ -0,0 +1,265 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use crate::dataset::{
+    csv_line, parse_bool, parse_cabin, parse_passenger_id, Category, DestinationPlanet, HomePlanet,
+    TitanicItemRaw,
+};
+use crate::error::DatasetError;
+
+/// Spending above this many standard deviations from the column mean is reported as an outlier
+const OUTLIER_STDDEVS: f32 = 4.0;
+
+/// Columns of the competition CSVs in file order
+const COLUMNS: [&str; 14] = [
+    "PassengerId",
+    "HomePlanet",
+    "CryoSleep",
+    "Cabin",
+    "Destination",
+    "Age",
+    "VIP",
+    "RoomService",
+    "FoodCourt",
+    "ShoppingMall",
+    "Spa",
+    "VRDeck",
+    "Name",
+    "Transported",
+];
+
+#[derive(Debug, Default)]
+pub struct ColumnReport {
+    pub name: &'static str,
+    pub missing: usize,
+    /// Values we don't know how to interpret, with how often they occur
+    pub invalid: BTreeMap<String, usize>,
+    /// Negative numbers, only counted for numeric columns
+    pub negative: Option<usize>,
+    /// Values far above the rest of the column, only counted for numeric columns
+    pub outliers: Option<usize>,
+}
+
+impl ColumnReport {
+    fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            ..Default::default()
+        }
+    }
+
+    fn check<T>(&mut self, value: Option<&str>, parse: impl Fn(&str) -> Result<T, DatasetError>) {
+        match value {
+            None | Some("") => self.missing += 1,
+            Some(value) => {
+                if parse(value).is_err() {
+                    *self.invalid.entry(value.to_string()).or_default() += 1;
+                }
+            }
+        }
+    }
+
+    fn check_numeric(&mut self, values: &[Option<f32>]) {
+        let present: Vec<f32> = values.iter().flatten().copied().collect();
+        self.missing += values.len() - present.len();
+        self.negative = Some(present.iter().filter(|value| **value < 0.0).count());
+
+        let count = present.len().max(1) as f32;
+        let mean = present.iter().sum::<f32>() / count;
+        let stddev = (present
+            .iter()
+            .map(|value| (value - mean).powi(2))
+            .sum::<f32>()
+            / count)
+            .sqrt();
+        self.outliers = Some(
+            present
+                .iter()
+                .filter(|value| stddev > 0.0 && **value > mean + OUTLIER_STDDEVS * stddev)
+                .count(),
+        );
+    }
+}
+
+fn column<'a>(
+    columns: &'a mut HashMap<&'static str, ColumnReport>,
+    name: &str,
+) -> &'a mut ColumnReport {
+    columns
+        .get_mut(name)
+        .expect("Every column should have a report")
+}
+
+/// Per column report of the problems found in a raw competition CSV
+#[derive(Debug)]
+pub struct ValidationReport {
+    pub source: String,
+    pub rows: usize,
+    pub columns: Vec<ColumnReport>,
+    /// Passenger ids that occur more than once, with how often they occur
+    pub duplicate_ids: BTreeMap<String, usize>,
+    /// Rows the CSV reader couldn't deserialize at all, these aren't part of the column counts
+    pub unreadable: Vec<DatasetError>,
+}
+
+impl ValidationReport {
+    pub fn from_reader<R: Read>(source: &str, reader: R) -> Result<Self, DatasetError> {
+        let mut reader = csv::ReaderBuilder::new()
+            .delimiter(b',')
+            .terminator(csv::Terminator::CRLF)
+            .from_reader(reader);
+
+        let headers = reader
+            .headers()
+            .map_err(|source| DatasetError::Csv { row: 1, source })?
+            .clone();
+
+        let mut items = Vec::new();
+        let mut unreadable = Vec::new();
+        for (index, record) in reader.records().enumerate() {
+            let row = match &record {
+                Ok(record) => csv_line(index, record.position()),
+                Err(err) => csv_line(index, err.position()),
+            };
+            let item =
+                record.and_then(|record| record.deserialize::<TitanicItemRaw>(Some(&headers)));
+            match item {
+                Ok(item) => items.push((row, item)),
+                Err(source) => unreadable.push(DatasetError::Csv { row, source }),
+            }
+        }
+
+        let mut columns: HashMap<&'static str, ColumnReport> = COLUMNS
+            .iter()
+            .map(|name| (*name, ColumnReport::new(*name)))
+            .collect();
+
+        let mut ids: HashMap<&str, usize> = HashMap::new();
+        for (row, item) in items.iter() {
+            let row = *row;
+            *ids.entry(item.passenger_id.as_str()).or_default() += 1;
+
+            column(&mut columns, "PassengerId").check(Some(item.passenger_id.as_str()), |value| {
+                parse_passenger_id(row, value)
+            });
+            column(&mut columns, "HomePlanet").check(Some(item.home_planet.as_str()), |value| {
+                HomePlanet::parse(row, value)
+            });
+            column(&mut columns, "CryoSleep").check(item.cryo_sleep.as_deref(), |value| {
+                parse_bool(row, "CryoSleep", value)
+            });
+            column(&mut columns, "Cabin")
+                .check(item.cabin.as_deref(), |value| parse_cabin(row, value));
+            column(&mut columns, "Destination").check(Some(item.destination.as_str()), |value| {
+                DestinationPlanet::parse(row, value)
+            });
+            column(&mut columns, "VIP")
+                .check(item.vip.as_deref(), |value| parse_bool(row, "VIP", value));
+            column(&mut columns, "Name").check(item.name.as_deref(), |_| Ok::<_, DatasetError>(()));
+            column(&mut columns, "Transported").check(item.transported.as_deref(), |value| {
+                parse_bool(row, "Transported", value)
+            });
+        }
+
+        let numeric: [(&str, fn(&TitanicItemRaw) -> Option<f32>); 6] = [
+            ("Age", |item| item.age),
+            ("RoomService", |item| item.room_service),
+            ("FoodCourt", |item| item.food_court),
+            ("ShoppingMall", |item| item.shopping_mall),
+            ("Spa", |item| item.spa),
+            ("VRDeck", |item| item.vr_deck),
+        ];
+        for (name, value) in numeric {
+            let values: Vec<Option<f32>> = items.iter().map(|(_, item)| value(item)).collect();
+            column(&mut columns, name).check_numeric(&values);
+        }
+
+        let duplicate_ids = ids
+            .into_iter()
+            .filter(|(_, count)| *count > 1)
+            .map(|(id, count)| (id.to_string(), count))
+            .collect();
+
+        // The submission CSV has no labels, so only report the columns the file actually has
+        let columns = COLUMNS
+            .iter()
+            .filter(|name| headers.iter().any(|header| header == **name))
+            .filter_map(|name| columns.remove(name))
+            .collect();
+
+        Ok(Self {
+            source: source.to_string(),
+            rows: items.len() + unreadable.len(),
+            columns,
+            duplicate_ids,
+            unreadable,
+        })
+    }
+
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, DatasetError> {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|source| DatasetError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+        Self::from_reader(&path.display().to_string(), BufReader::new(file))
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let optional = |count: Option<usize>| match count {
+            Some(count) => count.to_string(),
+            None => "-".to_string(),
+        };
+
+        writeln!(f, "== {} ({} rows) ==", self.source, self.rows)?;
+        writeln!(
+            f,
+            "{:<14}{:>9}{:>9}{:>10}{:>10}",
+            "Column", "Missing", "Invalid", "Negative", "Outliers"
+        )?;
+        for column in self.columns.iter() {
+            writeln!(
+                f,
+                "{:<14}{:>9}{:>9}{:>10}{:>10}",
+                column.name,
+                column.missing,
+                column.invalid.values().sum::<usize>(),
+                optional(column.negative),
+                optional(column.outliers),
+            )?;
+        }
+
+        for column in self
+            .columns
+            .iter()
+            .filter(|column| !column.invalid.is_empty())
+        {
+            writeln!(f, "Invalid {} values:", column.name)?;
+            for (value, count) in column.invalid.iter() {
+                writeln!(f, "  {value:?} x{count}")?;
+            }
+        }
+
+        if !self.duplicate_ids.is_empty() {
+            writeln!(f, "Duplicate PassengerIds:")?;
+            for (id, count) in self.duplicate_ids.iter() {
+                writeln!(f, "  {id} x{count}")?;
+            }
+        }
+
+        if !self.unreadable.is_empty() {
+            writeln!(f, "Unreadable rows:")?;
+            for err in self.unreadable.iter() {
+                writeln!(f, "  {err}")?;
+            }
+        }
+
+        Ok(())
+    }
+}